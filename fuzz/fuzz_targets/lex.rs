@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mellow_lex::{Lex, SourceBuffer};
+
+fuzz_target!(|source: &str| {
+    for token in Lex::new(SourceBuffer::from(source)) {
+        let _ = token;
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use mellow_lex::{Lex, SourceBuffer};
+use mellow_parse::Parse;
+
+fuzz_target!(|source: &str| {
+    let lex = Lex::new(SourceBuffer::from(source));
+    for statement in Parse::new(lex.peekable()) {
+        if statement.is_err() {
+            break;
+        }
+    }
+});
@@ -1,71 +1,130 @@
-use std::vec::IntoIter;
+use std::fmt;
 
-use mellow_parse::{Expression, Statement};
+use crate::tac::Instruction;
 
-#[derive(Debug, Clone)]
-pub enum Link {
-    Direct(usize),
+/// How control leaves a block.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(usize),
     Branch {
-        condition: Expression,
+        condition: u64,
         true_: usize,
         false_: usize,
     },
+    /// The end of the program.
+    Exit,
 }
 
-#[derive(Debug, Clone)]
-pub struct Block {
-    body: Vec<Statement>,
-    next: Option<Link>,
-}
-
-impl Block {
-    pub fn new(body: Vec<Statement>) -> Self {
-        Self { body, next: None }
-    }
-
-    pub fn empty() -> Self {
-        Self::new(Vec::new())
-    }
-}
-
-impl Block {
-    pub fn to(&mut self, link: Link) {
-        self.next = Some(link);
+impl Terminator {
+    pub fn targets(&self) -> Vec<usize> {
+        match self {
+            Self::Jump(to) => vec![*to],
+            Self::Branch { true_, false_, .. } if true_ == false_ => vec![*true_],
+            Self::Branch { true_, false_, .. } => vec![*true_, *false_],
+            Self::Exit => Vec::new(),
+        }
     }
 
-    pub fn direct(&mut self, to: usize) {
-        self.next = Some(Link::Direct(to));
+    pub fn targets_mut(&mut self) -> Vec<&mut usize> {
+        match self {
+            Self::Jump(to) => vec![to],
+            Self::Branch { true_, false_, .. } => vec![true_, false_],
+            Self::Exit => Vec::new(),
+        }
     }
 
-    pub fn branch(&mut self, condition: Expression, true_: usize, false_: usize) {
-        self.next = Some(Link::Branch {
-            condition,
-            true_,
-            false_,
-        })
+    pub fn operands(&self) -> Vec<u64> {
+        match self {
+            Self::Branch { condition, .. } => vec![*condition],
+            _ => Vec::new(),
+        }
     }
 
-    pub fn next(&self) -> Option<&Link> {
-        self.next.as_ref()
+    pub fn operands_mut(&mut self) -> Vec<&mut u64> {
+        match self {
+            Self::Branch { condition, .. } => vec![condition],
+            _ => Vec::new(),
+        }
     }
 }
 
-impl IntoIterator for Block {
-    type Item = Statement;
-    type IntoIter = IntoIter<Statement>;
+/// The part of a statement a block was built for, which names its code in
+/// warnings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// The body of a branch of an `if`: 0 is the `if` itself, and the others
+    /// are its `or` branches.
+    Branch(usize),
+    /// What runs when a branch is not taken: the condition of the next `or`,
+    /// or the `else` body after the last branch.
+    Otherwise { branch: usize, last: bool },
+    /// The code after an `if`.
+    AfterIf,
+    /// The condition of a `while`, checked before every pass.
+    Condition,
+    /// The body of a `while`.
+    Loop,
+    /// The code after a `while`.
+    AfterLoop,
+}
 
-    fn into_iter(self) -> Self::IntoIter {
-        self.body.into_iter()
+impl fmt::Display for Origin {
+    /// Why the code is unreachable, given that the statement itself is not.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = |branch| if branch == 0 { "an `if`" } else { "an `or`" };
+        match self {
+            Self::Branch(branch) => write!(
+                f,
+                "the body of {} whose condition is always false",
+                kind(*branch)
+            ),
+            Self::Otherwise { branch: 0, last } => {
+                let otherwise = if *last { "the `else`" } else { "every other" };
+                write!(
+                    f,
+                    "{otherwise} branch of an `if` whose condition is always true"
+                )
+            }
+            Self::Otherwise { last, .. } => {
+                let otherwise = if *last { "the `else`" } else { "every" };
+                write!(
+                    f,
+                    "{otherwise} branch after an `or` whose condition is always true"
+                )
+            }
+            Self::AfterIf => write!(f, "the code after an `if` whose branches never finish"),
+            Self::Condition => write!(f, "the condition of a `while`"),
+            Self::Loop => write!(f, "the body of a `while` whose condition is always false"),
+            Self::AfterLoop => write!(f, "the code after a `while` whose condition is always true"),
+        }
     }
 }
 
-pub struct BlockRange {
-    pub start: usize,
-    pub end: usize,
+/// A basic block: straight-line TAC ended by a terminator. `body` never holds
+/// labels or jumps.
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub body: Vec<Instruction>,
+    pub terminator: Terminator,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+    /// What the block was built for, and the block its statement starts in.
+    /// `None` for the entry, which is always reachable.
+    pub origin: Option<(Origin, usize)>,
 }
 
-impl BlockRange {
-    pub fn new(start: usize, end: usize) -> Self {
-        Self { start, end }
+impl Block {
+    pub fn new(body: Vec<Instruction>, terminator: Terminator) -> Self {
+        Self {
+            body,
+            terminator,
+            successors: Vec::new(),
+            predecessors: Vec::new(),
+            origin: None,
+        }
+    }
+
+    pub fn empty() -> Self {
+        Self::new(Vec::new(), Terminator::Exit)
     }
 }
@@ -1,10 +1,116 @@
 mod block;
-mod constructor;
-pub use block::{Block, Link};
-pub use constructor::Constructor;
-use mellow_parse::Statement;
-
-pub fn construct(source: Vec<Statement>) -> Vec<Block> {
-    let constructor = Constructor::new();
-    constructor.construct(source)
+pub use block::{Block, Origin, Terminator};
+
+use crate::{dominator::Dominators, tac::Instruction};
+
+/// Control flow graph of TAC. Block 0 is the entry, and block ids double as
+/// TAC labels.
+#[derive(Debug, Clone)]
+pub struct Graph {
+    pub blocks: Vec<Block>,
+}
+
+impl Graph {
+    pub fn new(blocks: Vec<Block>) -> Self {
+        let mut graph = Self { blocks };
+        graph.link();
+        graph
+    }
+
+    /// Recomputes successors and predecessors from the terminators.
+    pub fn link(&mut self) {
+        for block in self.blocks.iter_mut() {
+            block.successors = block.terminator.targets();
+            block.predecessors.clear();
+        }
+        for id in 0..self.blocks.len() {
+            for successor in self.blocks[id].successors.clone() {
+                self.blocks[successor].predecessors.push(id);
+            }
+        }
+    }
+
+    pub fn successors(&self) -> Vec<Vec<usize>> {
+        self.blocks
+            .iter()
+            .map(|block| block.successors.clone())
+            .collect()
+    }
+
+    pub fn dominators(&self) -> Dominators {
+        Dominators::new(&self.successors())
+    }
+
+    /// Ids of blocks that can be reached from the entry.
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut stack = vec![0];
+        while let Some(id) = stack.pop() {
+            if id >= self.blocks.len() || visited[id] {
+                continue;
+            }
+            visited[id] = true;
+            stack.extend(&self.blocks[id].successors);
+        }
+        visited
+    }
+
+    /// Deletes the blocks that are not kept and renumbers the others.
+    pub fn retain(&mut self, keep: &[bool]) {
+        let mut ids = Vec::with_capacity(keep.len());
+        let mut next = 0;
+        for kept in keep {
+            ids.push(next);
+            next += *kept as usize;
+        }
+        let blocks = std::mem::take(&mut self.blocks);
+        for (id, mut block) in blocks.into_iter().enumerate() {
+            if !keep[id] {
+                continue;
+            }
+            for target in block.terminator.targets_mut() {
+                *target = ids[*target];
+            }
+            // a statement that starts in a deleted block is deleted whole
+            if let Some((_, from)) = &mut block.origin {
+                *from = ids[*from];
+            }
+            self.blocks.push(block);
+        }
+        self.link();
+    }
+
+    /// Lays the graph out as linear TAC, with a label for every block.
+    pub fn linearize(self) -> Vec<Instruction> {
+        let end = self.blocks.len();
+        let mut output = Vec::new();
+        let mut exits = false;
+        for (id, block) in self.blocks.into_iter().enumerate() {
+            output.push(Instruction::Label(id as u64));
+            output.extend(block.body);
+            match block.terminator {
+                Terminator::Jump(to) => output.push(Instruction::Jump(to as u64)),
+                Terminator::Branch {
+                    condition,
+                    true_,
+                    false_,
+                } => {
+                    output.push(Instruction::JumpIf {
+                        condition,
+                        to: true_ as u64,
+                    });
+                    output.push(Instruction::Jump(false_ as u64));
+                }
+                Terminator::Exit if id + 1 != end => {
+                    exits = true;
+                    output.push(Instruction::Jump(end as u64));
+                }
+                Terminator::Exit => {}
+            }
+        }
+        if exits {
+            output.push(Instruction::Label(end as u64));
+        }
+        output
+    }
 }
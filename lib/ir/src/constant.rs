@@ -0,0 +1,93 @@
+use mellow_parse::*;
+
+use crate::{SymbolTable, symbol_table::TypeError, tac::evaluate};
+
+/// Evaluates the value of a `const` at compile time. Booleans are `0` and `1`,
+/// just like at runtime.
+pub struct Evaluator<'a> {
+    table: &'a SymbolTable,
+}
+
+impl<'a> Evaluator<'a> {
+    pub fn new(table: &'a SymbolTable) -> Self {
+        Self { table }
+    }
+
+    pub fn evaluate(mut self, expression: &Expression) -> Result<i64, TypeError> {
+        expression.visit(&mut self)
+    }
+}
+
+const UNKNOWN: TypeError = TypeError("constant value must be known at compile time");
+const OVERFLOW: TypeError = TypeError("constant value overflows i64");
+
+impl VisitExpression for Evaluator<'_> {
+    type Output = Result<i64, TypeError>;
+
+    fn integer(&mut self, node: &Integer) -> Self::Output {
+        Ok(node.value)
+    }
+
+    fn identifier(&mut self, node: &Identifier) -> Self::Output {
+        match self.table.get_constant(node) {
+            Some(meta) => Ok(meta.value),
+            None => Err(UNKNOWN),
+        }
+    }
+
+    fn boolean(&mut self, node: &Boolean) -> Self::Output {
+        Ok(node.value as i64)
+    }
+
+    fn string(&mut self, _node: &Str) -> Self::Output {
+        Err(TypeError("constant must be an integer, a boolean or a character"))
+    }
+
+    fn char(&mut self, node: &Char) -> Self::Output {
+        Ok(node.value as i64)
+    }
+
+    fn binary(&mut self, node: &Binary) -> Self::Output {
+        let left = node.left.visit(self)?;
+        let right = node.right.visit(self)?;
+        if matches!(node.kind, BinaryKind::Divide | BinaryKind::Remainder) && right == 0 {
+            return Err(TypeError("division by zero in a constant"));
+        }
+        let value = match node.kind {
+            BinaryKind::Add => left.checked_add(right),
+            BinaryKind::Subtract => left.checked_sub(right),
+            BinaryKind::Multiply => left.checked_mul(right),
+            BinaryKind::Divide => left.checked_div(right),
+            BinaryKind::Remainder => left.checked_rem(right),
+            BinaryKind::Power => evaluate::checked_power(left, right),
+            BinaryKind::Greater => Some((left > right) as i64),
+            BinaryKind::Less => Some((left < right) as i64),
+            BinaryKind::Equal => Some((left == right) as i64),
+        };
+        value.ok_or(OVERFLOW)
+    }
+
+    fn unary(&mut self, node: &Unary) -> Self::Output {
+        let inner = node.inner.visit(self)?;
+        match node.kind {
+            UnaryKind::Negate => inner.checked_neg().ok_or(OVERFLOW),
+            UnaryKind::Not => Ok((inner == 0) as i64),
+        }
+    }
+
+    fn if_(&mut self, _node: &If<Expression>) -> Self::Output {
+        Err(UNKNOWN)
+    }
+
+    fn call(&mut self, _node: &Call) -> Self::Output {
+        Err(UNKNOWN)
+    }
+
+    fn index(&mut self, _node: &Index) -> Self::Output {
+        Err(UNKNOWN)
+    }
+
+    fn field(&mut self, _node: &Field) -> Self::Output {
+        Err(UNKNOWN)
+    }
+}
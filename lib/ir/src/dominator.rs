@@ -0,0 +1,148 @@
+use std::collections::BTreeSet;
+
+/// Dominator tree of a graph whose entry is node 0, built with the iterative
+/// algorithm of Cooper, Harvey and Kennedy. Nodes that cannot be reached from
+/// the entry have no immediate dominator.
+#[derive(Debug)]
+pub struct Dominators {
+    immediate: Vec<Option<usize>>,
+    children: Vec<Vec<usize>>,
+    frontier: Vec<BTreeSet<usize>>,
+}
+
+fn postorder(successors: &[Vec<usize>]) -> Vec<usize> {
+    let mut output = Vec::new();
+    let mut visited = vec![false; successors.len()];
+    let mut stack = vec![(0, 0)];
+    visited[0] = true;
+    while let Some((node, next)) = stack.pop() {
+        if let Some(&successor) = successors[node].get(next) {
+            stack.push((node, next + 1));
+            if !visited[successor] {
+                visited[successor] = true;
+                stack.push((successor, 0));
+            }
+        } else {
+            output.push(node);
+        }
+    }
+    output
+}
+
+impl Dominators {
+    pub fn new(successors: &[Vec<usize>]) -> Self {
+        let length = successors.len();
+        let mut predecessors = vec![Vec::new(); length];
+        for (node, targets) in successors.iter().enumerate() {
+            for target in targets {
+                predecessors[*target].push(node);
+            }
+        }
+
+        let mut immediate = vec![None; length];
+        if length == 0 {
+            return Self {
+                immediate,
+                children: Vec::new(),
+                frontier: Vec::new(),
+            };
+        }
+
+        let postorder = postorder(successors);
+        let mut order = vec![usize::MAX; length];
+        for (index, node) in postorder.iter().enumerate() {
+            order[*node] = index;
+        }
+        let intersect = |immediate: &[Option<usize>], mut a: usize, mut b: usize| {
+            while a != b {
+                while order[a] < order[b] {
+                    a = immediate[a].unwrap();
+                }
+                while order[b] < order[a] {
+                    b = immediate[b].unwrap();
+                }
+            }
+            a
+        };
+
+        immediate[0] = Some(0);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in postorder.iter().rev().skip(1) {
+                let mut processed = predecessors[*node]
+                    .iter()
+                    .filter(|predecessor| immediate[**predecessor].is_some());
+                let Some(first) = processed.next() else {
+                    continue;
+                };
+                let new = processed.fold(*first, |new, predecessor| {
+                    intersect(&immediate, *predecessor, new)
+                });
+                if immediate[*node] != Some(new) {
+                    immediate[*node] = Some(new);
+                    changed = true;
+                }
+            }
+        }
+
+        let mut children = vec![Vec::new(); length];
+        for (node, parent) in immediate.iter().enumerate().skip(1) {
+            if let Some(parent) = parent {
+                children[*parent].push(node);
+            }
+        }
+
+        let mut frontier = vec![BTreeSet::new(); length];
+        for (node, predecessors) in predecessors.iter().enumerate() {
+            let Some(dominator) = immediate[node] else {
+                continue;
+            };
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for predecessor in predecessors {
+                let mut runner = *predecessor;
+                if immediate[runner].is_none() {
+                    continue;
+                }
+                while runner != dominator {
+                    frontier[runner].insert(node);
+                    runner = immediate[runner].unwrap();
+                }
+            }
+        }
+
+        immediate[0] = None;
+        Self {
+            immediate,
+            children,
+            frontier,
+        }
+    }
+
+    pub fn immediate(&self, node: usize) -> Option<usize> {
+        self.immediate[node]
+    }
+
+    /// Nodes immediately dominated by `node`.
+    pub fn children(&self, node: usize) -> &[usize] {
+        &self.children[node]
+    }
+
+    pub fn frontier(&self, node: usize) -> &BTreeSet<usize> {
+        &self.frontier[node]
+    }
+
+    pub fn dominates(&self, a: usize, mut b: usize) -> bool {
+        loop {
+            if a == b {
+                return true;
+            }
+            match self.immediate[b] {
+                Some(parent) => b = parent,
+                None => return false,
+            }
+        }
+    }
+}
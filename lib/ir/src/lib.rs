@@ -1,4 +1,7 @@
 pub mod cfg;
+pub mod constant;
+pub mod dominator;
+pub mod ssa;
 pub mod symbol_table;
 pub mod tac;
 pub use symbol_table::SymbolTable;
@@ -0,0 +1,359 @@
+//! Variables are promoted to SSA values: every `Get` is replaced with the
+//! value of the variable that reaches it, and a `Phi` is placed wherever
+//! different values meet. `Set`s are kept as stores, so memory always holds
+//! the current value of a variable and leaving SSA only takes reloads.
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
+
+use mellow_parse::Identifier;
+
+use crate::{
+    cfg::{Block, Graph},
+    dominator::Dominators,
+    tac::Instruction,
+};
+
+fn next_temporary(blocks: &[Block]) -> u64 {
+    blocks
+        .iter()
+        .flat_map(|block| &block.body)
+        .filter_map(Instruction::result)
+        .max()
+        .map_or(0, |id| id + 1)
+}
+
+/// Index of the first instruction after the leading `Phi`s.
+fn after_phis(block: &Block) -> usize {
+    block
+        .body
+        .iter()
+        .take_while(|instruction| matches!(instruction, Instruction::Phi { .. }))
+        .count()
+}
+
+fn rename(replaced: &HashMap<u64, u64>, operands: Vec<&mut u64>) {
+    for operand in operands {
+        if let Some(replacement) = replaced.get(operand) {
+            *operand = *replacement;
+        }
+    }
+}
+
+/// A step of an explicit walk over the dominator tree, which can be as deep
+/// as the program is long.
+enum Step<T> {
+    Enter(usize),
+    /// Undoes what entering a block did, after the blocks it dominates.
+    Exit(T),
+}
+
+struct Renamer<'a> {
+    blocks: Vec<Block>,
+    dominators: &'a Dominators,
+    stacks: HashMap<Identifier, Vec<u64>>,
+    replaced: HashMap<u64, u64>,
+    temporary: u64,
+}
+
+impl Renamer<'_> {
+    /// Renames the values in block `id` and returns the variables it pushed,
+    /// which stay on their stacks for the blocks it dominates.
+    fn block(&mut self, id: usize) -> Vec<Identifier> {
+        let mut pushed = Vec::new();
+        let body = std::mem::take(&mut self.blocks[id].body);
+        let mut output = Vec::with_capacity(body.len());
+        for mut instruction in body {
+            rename(&self.replaced, instruction.operands_mut());
+            match &instruction {
+                Instruction::Phi { to, identifier, .. } => {
+                    self.push(*identifier, *to, &mut pushed);
+                }
+                Instruction::Get { to, identifier } => {
+                    if let Some(current) = self.current(identifier) {
+                        self.replaced.insert(*to, current);
+                        continue;
+                    }
+                    // not set on every path here, so keep reading memory
+                    self.push(*identifier, *to, &mut pushed);
+                }
+                Instruction::Set { identifier, from } => {
+                    self.push(*identifier, *from, &mut pushed);
+                }
+                _ => {}
+            }
+            output.push(instruction);
+        }
+        self.blocks[id].body = output;
+        rename(&self.replaced, self.blocks[id].terminator.operands_mut());
+
+        for successor in self.blocks[id].successors.clone() {
+            self.incoming(id, successor);
+        }
+        pushed
+    }
+
+    /// Renames every block, walking the dominator tree from the entry.
+    fn rename(&mut self) {
+        let mut walk = vec![Step::Enter(0)];
+        while let Some(step) = walk.pop() {
+            match step {
+                Step::Enter(id) => {
+                    let pushed = self.block(id);
+                    walk.push(Step::Exit(pushed));
+                    let children = self.dominators.children(id).iter().rev();
+                    walk.extend(children.map(|child| Step::Enter(*child)));
+                }
+                Step::Exit(pushed) => {
+                    for identifier in pushed {
+                        self.stacks.get_mut(&identifier).unwrap().pop();
+                    }
+                }
+            }
+        }
+    }
+
+    fn incoming(&mut self, id: usize, successor: usize) {
+        let label = id as u64;
+        let count = after_phis(&self.blocks[successor]);
+        for index in 0..count {
+            let Instruction::Phi { identifier, .. } = self.blocks[successor].body[index] else {
+                unreachable!();
+            };
+            let value = match self.current(&identifier) {
+                Some(value) => value,
+                None => {
+                    let to = self.temporary;
+                    self.temporary += 1;
+                    self.blocks[id]
+                        .body
+                        .push(Instruction::Get { to, identifier });
+                    to
+                }
+            };
+            if let Instruction::Phi { from, .. } = &mut self.blocks[successor].body[index] {
+                from.push((label, value));
+            }
+        }
+    }
+
+    fn current(&self, identifier: &Identifier) -> Option<u64> {
+        self.stacks
+            .get(identifier)
+            .and_then(|stack| stack.last())
+            .copied()
+    }
+
+    fn push(&mut self, identifier: Identifier, value: u64, pushed: &mut Vec<Identifier>) {
+        self.stacks.entry(identifier).or_default().push(value);
+        pushed.push(identifier);
+    }
+}
+
+/// Converts TAC into SSA form.
+pub fn construct(source: Graph) -> Graph {
+    let dominators = source.dominators();
+    let mut blocks = source.blocks;
+    let mut temporary = next_temporary(&blocks);
+
+    let mut definitions: HashMap<Identifier, BTreeSet<usize>> = HashMap::new();
+    for (id, block) in blocks.iter().enumerate() {
+        for instruction in &block.body {
+            if let Instruction::Set { identifier, .. } = instruction {
+                definitions.entry(*identifier).or_default().insert(id);
+            }
+        }
+    }
+    let mut identifiers: Vec<_> = definitions.keys().copied().collect();
+    identifiers.sort();
+
+    for identifier in identifiers {
+        let mut placed = HashSet::new();
+        let mut work: Vec<usize> = definitions[&identifier].iter().copied().collect();
+        while let Some(id) = work.pop() {
+            for frontier in dominators.frontier(id) {
+                if placed.insert(*frontier) {
+                    let phi = Instruction::Phi {
+                        to: temporary,
+                        identifier,
+                        from: Vec::new(),
+                    };
+                    temporary += 1;
+                    let position = after_phis(&blocks[*frontier]);
+                    blocks[*frontier].body.insert(position, phi);
+                    work.push(*frontier);
+                }
+            }
+        }
+    }
+
+    let mut renamer = Renamer {
+        blocks,
+        dominators: &dominators,
+        stacks: HashMap::new(),
+        replaced: HashMap::new(),
+        temporary,
+    };
+    renamer.rename();
+    Graph::new(renamer.blocks)
+}
+
+/// A value from another block that no variable holds, so it cannot be
+/// reloaded. SSA construction never produces one.
+#[derive(Debug)]
+pub struct DestructError(pub u64);
+
+impl Display for DestructError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "internal error: #{} comes from another block, but no variable holds it",
+            self.0
+        )
+    }
+}
+
+/// Which variable holds each value at the current point of the dominator
+/// tree walk. Bindings are undone in reverse when the walk leaves a block.
+#[derive(Default)]
+struct Holders {
+    /// The values a variable was bound to, the current one last.
+    values: HashMap<Identifier, Vec<u64>>,
+    /// The variables bound to a value, in dominator tree order.
+    variables: HashMap<u64, Vec<Identifier>>,
+}
+
+impl Holders {
+    fn bind(&mut self, identifier: Identifier, value: u64) {
+        self.values.entry(identifier).or_default().push(value);
+        self.variables.entry(value).or_default().push(identifier);
+    }
+
+    fn unbind(&mut self, identifier: Identifier, value: u64) {
+        self.values.get_mut(&identifier).and_then(Vec::pop);
+        self.variables.get_mut(&value).and_then(Vec::pop);
+    }
+
+    /// The latest variable bound to `value` that still holds it.
+    fn holder(&self, value: u64) -> Option<Identifier> {
+        self.variables
+            .get(&value)?
+            .iter()
+            .rev()
+            .find(|identifier| self.values[*identifier].last() == Some(&value))
+            .copied()
+    }
+}
+
+/// The variable an instruction binds to a value, if any.
+fn binding(instruction: &Instruction) -> Option<(Identifier, u64)> {
+    match *instruction {
+        Instruction::Set {
+            identifier,
+            from: value,
+        }
+        | Instruction::Get {
+            to: value,
+            identifier,
+        } => Some((identifier, value)),
+        _ => None,
+    }
+}
+
+/// Converts SSA back into TAC where temporaries never outlive their block:
+/// `Phi`s become loads, and values that come from other blocks are reloaded
+/// from the variable that holds them.
+pub fn destruct(source: Graph) -> Result<Graph, DestructError> {
+    let dominators = source.dominators();
+    let mut blocks = source.blocks;
+    let mut temporary = next_temporary(&blocks);
+    let mut holders = Holders::default();
+
+    let mut walk: Vec<Step<Vec<(Identifier, u64)>>> = vec![Step::Enter(0)];
+    while let Some(step) = walk.pop() {
+        let id = match step {
+            Step::Enter(id) => id,
+            Step::Exit(bound) => {
+                for (identifier, value) in bound.into_iter().rev() {
+                    holders.unbind(identifier, value);
+                }
+                continue;
+            }
+        };
+        let block = &mut blocks[id];
+        let phis = after_phis(block);
+        let mut bound = Vec::new();
+        // a `Phi` means the variable may hold something else on entry
+        for instruction in &mut block.body[..phis] {
+            if let Instruction::Phi { to, identifier, .. } = *instruction {
+                *instruction = Instruction::Get { to, identifier };
+                holders.bind(identifier, to);
+                bound.push((identifier, to));
+            }
+        }
+
+        let mut reload = Reload {
+            holders: &holders,
+            defined: HashSet::new(),
+            reloaded: HashMap::new(),
+            loads: Vec::new(),
+            temporary: &mut temporary,
+        };
+        for instruction in block.body.iter_mut() {
+            reload.operands(instruction.operands_mut())?;
+            if let Some(to) = instruction.result() {
+                reload.defined.insert(to);
+            }
+        }
+        reload.operands(block.terminator.operands_mut())?;
+        let loads = reload.loads;
+
+        for (identifier, value) in block.body[phis..].iter().filter_map(binding) {
+            holders.bind(identifier, value);
+            bound.push((identifier, value));
+        }
+        block.body.splice(0..0, loads);
+
+        walk.push(Step::Exit(bound));
+        let children = dominators.children(id).iter().rev();
+        walk.extend(children.map(|child| Step::Enter(*child)));
+    }
+    Ok(Graph::new(blocks))
+}
+
+struct Reload<'a> {
+    holders: &'a Holders,
+    defined: HashSet<u64>,
+    reloaded: HashMap<u64, u64>,
+    loads: Vec<Instruction>,
+    temporary: &'a mut u64,
+}
+
+impl Reload<'_> {
+    /// Points operands defined in another block at a load of their variable.
+    fn operands(&mut self, operands: Vec<&mut u64>) -> Result<(), DestructError> {
+        for operand in operands {
+            if self.defined.contains(operand) {
+                continue;
+            }
+            let to = match self.reloaded.get(operand) {
+                Some(to) => *to,
+                None => {
+                    let identifier = self
+                        .holders
+                        .holder(*operand)
+                        .ok_or(DestructError(*operand))?;
+                    let to = *self.temporary;
+                    *self.temporary += 1;
+                    self.loads.push(Instruction::Get { to, identifier });
+                    self.reloaded.insert(*operand, to);
+                    to
+                }
+            };
+            *operand = to;
+        }
+        Ok(())
+    }
+}
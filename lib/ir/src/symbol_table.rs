@@ -1,76 +1,245 @@
-use std::collections::{HashMap, hash_map::Iter};
+use std::collections::{BTreeMap, btree_map::Iter};
 
 use mellow_parse::*;
 
+use crate::constant::Evaluator;
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Type {
     I64,
     I32,
     String,
     Boolean,
+    Char,
+}
+
+impl Type {
+    /// Suffix of the runtime routines that print values of this type.
+    pub fn suffix(&self) -> &'static str {
+        match self {
+            Self::I64 | Self::I32 => "i64",
+            Self::String => "s",
+            Self::Boolean => "bool",
+            Self::Char => "c",
+        }
+    }
 }
 
 pub struct TypeChecker {
     table: SymbolTable,
+    // how many `if` and `while` bodies the current statement is in
+    depth: usize,
 }
 
 impl TypeChecker {
     pub fn new() -> Self {
         Self {
             table: SymbolTable::new(),
+            depth: 0,
         }
     }
 }
 
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug)]
 pub struct TypeError(pub &'static str);
 
 impl TypeChecker {
-    pub fn construct(mut self, source: &Vec<Statement>) -> Result<SymbolTable, TypeError> {
+    pub fn construct(mut self, source: &[Statement]) -> Result<SymbolTable, TypeError> {
         for statement in source {
-            statement.clone().visit(&mut self, &mut ())?;
+            statement.visit(&mut self, &mut ())?;
         }
         Ok(self.table)
     }
+
+    fn body(&mut self, body: &Body) -> Result<(), TypeError> {
+        self.depth += 1;
+        for statement in body {
+            statement.visit(self, &mut ())?;
+        }
+        self.depth -= 1;
+        Ok(())
+    }
+
+    fn condition(&mut self, condition: &Expression) -> Result<(), TypeError> {
+        match condition.visit(self)? {
+            Type::Boolean => Ok(()),
+            _ => Err(TypeError("condition must be a boolean")),
+        }
+    }
 }
 
 impl VisitStatement for TypeChecker {
     type Output = Result<(), TypeError>;
     type Context = ();
 
-    fn let_(&mut self, node: Let, _context: &mut Self::Context) -> Self::Output {
+    fn let_(&mut self, node: &Let, _context: &mut Self::Context) -> Self::Output {
+        if self.table.get_constant(&node.identifier).is_some() {
+            return Err(TypeError("constant is already defined"));
+        }
+        let type_ = node.value.visit(self)?;
+        // bindings of a name share one location, so after a body it holds
+        // either of them, and a loop body reads its own from the last pass
+        if let Some(meta) = self.table.get_variable(&node.identifier)
+            && self.depth > 0
+            && meta.type_ != type_
+        {
+            return Err(TypeError("shadowing in a nested body must keep the type"));
+        }
         let meta = VariableMeta {
             mutable: node.mutable,
-            type_: node.value.visit(self)?,
+            type_,
         };
         self.table.insert_variable(node.identifier, meta);
         Ok(())
     }
 
-    fn assign(&mut self, node: Assign, _context: &mut Self::Context) -> Self::Output {
+    fn const_(&mut self, node: &Const, _context: &mut Self::Context) -> Self::Output {
+        if self.table.get_constant(&node.identifier).is_some()
+            || self.table.get_variable(&node.identifier).is_some()
+        {
+            return Err(TypeError("constant is already defined"));
+        }
+        let type_ = node.value.visit(self)?;
+        let value = Evaluator::new(&self.table).evaluate(&node.value)?;
+        self.table
+            .insert_constant(node.identifier, ConstantMeta { type_, value });
+        Ok(())
+    }
+
+    fn assign(&mut self, node: &Assign, _context: &mut Self::Context) -> Self::Output {
+        if self.table.get_constant(&node.identifier).is_some() {
+            return Err(TypeError("cannot assign to a constant"));
+        }
+        let type_ = node.value.visit(self)?;
         if let Some(meta) = self.table.get_variable(&node.identifier) {
             if !meta.mutable {
                 return Err(TypeError("cannot assign twice to an immutable variable"));
             }
+            if meta.type_ != type_ {
+                return Err(TypeError("assigned value must have the type of the variable"));
+            }
+            if node.operator.is_some() && type_ != Type::I64 {
+                return Err(TypeError("arithmetic operands must be integers"));
+            }
             return Ok(());
         }
         Err(TypeError("variable is not found"))
     }
+
+    fn if_(&mut self, node: &If<Body>, _context: &mut Self::Context) -> Self::Output {
+        for branch in std::iter::once(&node.if_).chain(&node.or) {
+            self.condition(&branch.condition)?;
+            self.body(&branch.body)?;
+        }
+        if let Some(body) = &node.else_ {
+            self.body(body)?;
+        }
+        Ok(())
+    }
+
+    fn while_(&mut self, node: &While, _context: &mut Self::Context) -> Self::Output {
+        self.condition(&node.condition)?;
+        self.body(&node.body)
+    }
+
+    fn debug(&mut self, node: &Debug, _context: &mut Self::Context) -> Self::Output {
+        for value in &node.values {
+            value.visit(self)?;
+        }
+        Ok(())
+    }
 }
 
 impl VisitExpression for TypeChecker {
     type Output = Result<Type, TypeError>;
 
-    fn integer(&mut self, node: Integer) -> Self::Output {
+    fn integer(&mut self, _node: &Integer) -> Self::Output {
         Ok(Type::I64) // TODO
     }
 
-    fn identifier(&mut self, node: Identifier) -> Self::Output {
-        if let Some(meta) = self.table.get_variable(&node) {
+    fn identifier(&mut self, node: &Identifier) -> Self::Output {
+        if let Some(meta) = self.table.get_constant(node) {
+            return Ok(meta.type_.clone());
+        }
+        if let Some(meta) = self.table.get_variable(node) {
             return Ok(meta.type_.clone());
         }
         Err(TypeError("variable is not found"))
     }
+
+    fn boolean(&mut self, _node: &Boolean) -> Self::Output {
+        Ok(Type::Boolean)
+    }
+
+    fn string(&mut self, _node: &Str) -> Self::Output {
+        Ok(Type::String)
+    }
+
+    fn char(&mut self, _node: &Char) -> Self::Output {
+        Ok(Type::Char)
+    }
+
+    fn binary(&mut self, node: &Binary) -> Self::Output {
+        let left = node.left.visit(self)?;
+        let right = node.right.visit(self)?;
+        match node.kind {
+            BinaryKind::Add
+            | BinaryKind::Subtract
+            | BinaryKind::Multiply
+            | BinaryKind::Divide
+            | BinaryKind::Remainder
+            | BinaryKind::Power => {
+                if left != Type::I64 || right != Type::I64 {
+                    return Err(TypeError("arithmetic operands must be integers"));
+                }
+                Ok(Type::I64)
+            }
+            BinaryKind::Greater | BinaryKind::Less => {
+                if left != Type::I64 || right != Type::I64 {
+                    return Err(TypeError("ordered operands must be integers"));
+                }
+                Ok(Type::Boolean)
+            }
+            BinaryKind::Equal => {
+                if left != right {
+                    return Err(TypeError("compared values must have the same type"));
+                }
+                Ok(Type::Boolean)
+            }
+        }
+    }
+
+    fn unary(&mut self, node: &Unary) -> Self::Output {
+        let inner = node.inner.visit(self)?;
+        match (&node.kind, inner) {
+            (UnaryKind::Negate, Type::I64) => Ok(Type::I64),
+            (UnaryKind::Negate, _) => Err(TypeError("negated value must be an integer")),
+            (UnaryKind::Not, Type::Boolean) => Ok(Type::Boolean),
+            (UnaryKind::Not, _) => Err(TypeError("inverted value must be a boolean")),
+        }
+    }
+
+    fn if_(&mut self, _node: &If<Expression>) -> Self::Output {
+        Err(TypeError("if expressions are not supported yet"))
+    }
+
+    fn call(&mut self, _node: &Call) -> Self::Output {
+        Err(TypeError("function calls are not supported yet"))
+    }
+
+    fn index(&mut self, _node: &Index) -> Self::Output {
+        Err(TypeError("indexing is not supported yet"))
+    }
+
+    fn field(&mut self, _node: &Field) -> Self::Output {
+        Err(TypeError("field access is not supported yet"))
+    }
 }
 
 #[derive(Debug)]
@@ -79,6 +248,12 @@ pub struct VariableMeta {
     pub type_: Type,
 }
 
+#[derive(Debug)]
+pub struct ConstantMeta {
+    pub type_: Type,
+    pub value: i64,
+}
+
 #[derive(Debug)]
 pub struct FunctionMeta {
     pub external: bool,
@@ -86,19 +261,27 @@ pub struct FunctionMeta {
 
 #[derive(Debug)]
 pub struct SymbolTable {
-    variables: HashMap<Identifier, VariableMeta>,
-    functions: HashMap<Identifier, FunctionMeta>,
+    variables: BTreeMap<Identifier, VariableMeta>,
+    constants: BTreeMap<Identifier, ConstantMeta>,
+    functions: BTreeMap<Identifier, FunctionMeta>,
 }
 
 impl SymbolTable {
     pub fn new() -> Self {
         Self {
-            variables: HashMap::new(),
-            functions: HashMap::new(),
+            variables: BTreeMap::new(),
+            constants: BTreeMap::new(),
+            functions: BTreeMap::new(),
         }
     }
 }
 
+impl Default for SymbolTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl SymbolTable {
     pub fn insert_variable(&mut self, identifier: Identifier, meta: VariableMeta) {
         self.variables.insert(identifier, meta);
@@ -112,11 +295,25 @@ impl SymbolTable {
         self.variables.get_mut(identifier)
     }
 
-    pub fn variables(&self) -> Iter<Identifier, VariableMeta> {
+    pub fn variables(&self) -> Iter<'_, Identifier, VariableMeta> {
         self.variables.iter()
     }
 }
 
+impl SymbolTable {
+    pub fn insert_constant(&mut self, identifier: Identifier, meta: ConstantMeta) {
+        self.constants.insert(identifier, meta);
+    }
+
+    pub fn get_constant(&self, identifier: &Identifier) -> Option<&ConstantMeta> {
+        self.constants.get(identifier)
+    }
+
+    pub fn constants(&self) -> Iter<'_, Identifier, ConstantMeta> {
+        self.constants.iter()
+    }
+}
+
 impl SymbolTable {
     pub fn insert_function(&mut self, identifier: Identifier, meta: FunctionMeta) {
         self.functions.insert(identifier, meta);
@@ -130,19 +327,20 @@ impl SymbolTable {
         self.functions.get_mut(identifier)
     }
 
-    pub fn functions(&self) -> Iter<Identifier, FunctionMeta> {
+    pub fn functions(&self) -> Iter<'_, Identifier, FunctionMeta> {
         self.functions.iter()
     }
 }
 
-pub fn construct(source: &Vec<Statement>) -> Result<SymbolTable, TypeError> {
+pub fn construct(source: &[Statement]) -> Result<SymbolTable, TypeError> {
     let type_checker = TypeChecker::new();
     let mut table = type_checker.construct(source)?;
-    table.insert_function(
-        Identifier {
-            name: "debug_i64".into(),
-        },
-        FunctionMeta { external: true },
-    );
+    for type_ in [Type::I64, Type::String, Type::Boolean, Type::Char] {
+        for prefix in ["write", "debug"] {
+            let label = Identifier::from(format!("{prefix}_{}", type_.suffix()).as_str());
+            table.insert_function(label, FunctionMeta { external: true });
+        }
+    }
+    table.insert_function(Identifier::from("power_i64"), FunctionMeta { external: true });
     Ok(table)
 }
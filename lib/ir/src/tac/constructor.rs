@@ -1,25 +1,49 @@
+use std::collections::HashMap;
+
 use mellow_parse::*;
 
-use super::Instruction;
-use crate::cfg::{Block, Link};
+use super::{Instruction, evaluate};
+use crate::{
+    SymbolTable,
+    symbol_table::Type,
+    cfg::{Block, Graph, Origin, Terminator},
+};
 
 pub struct Constructor {
-    output: Vec<Instruction>,
+    blocks: Vec<Block>,
+    current: usize,
     temporary: u64,
+    // temporaries that hold the current value of a variable in this block
+    loaded: HashMap<Identifier, u64>,
+    constants: HashMap<Identifier, (i64, Type)>,
+    // checked types of variables and of the temporaries holding their values
+    variables: HashMap<Identifier, Type>,
+    types: HashMap<u64, Type>,
 }
 
 impl Constructor {
-    pub fn new() -> Self {
+    pub fn new(table: &SymbolTable) -> Self {
         Self {
-            output: Vec::new(),
+            blocks: vec![Block::empty()],
+            current: 0,
             temporary: 0,
+            loaded: HashMap::new(),
+            constants: table
+                .constants()
+                .map(|(identifier, meta)| (*identifier, (meta.value, meta.type_.clone())))
+                .collect(),
+            variables: table
+                .variables()
+                .map(|(identifier, meta)| (*identifier, meta.type_.clone()))
+                .collect(),
+            types: HashMap::new(),
         }
     }
 }
 
 impl Constructor {
     fn push(&mut self, instruction: Instruction) {
-        self.output.push(instruction);
+        self.blocks[self.current].body.push(instruction);
     }
 
     fn allocate(&mut self) -> u64 {
@@ -28,41 +52,93 @@ impl Constructor {
         id
     }
 
-    fn block(&mut self, value: Block) {
-        for statement in value.clone().into_iter() {
+    /// Adds an empty block, which ends the program until it gets a terminator.
+    /// `from` is the block the statement it belongs to starts in.
+    fn block(&mut self, origin: Origin, from: usize) -> usize {
+        let mut block = Block::empty();
+        block.origin = Some((origin, from));
+        self.blocks.push(block);
+        self.blocks.len() - 1
+    }
+
+    fn switch(&mut self, id: usize) {
+        self.current = id;
+        self.loaded.clear();
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current].terminator = terminator;
+    }
+
+    fn body(&mut self, body: &Body) {
+        for statement in body {
             statement.visit(self, &mut ());
         }
-        if let Some(next) = value.next() {
-            self.link(next.clone());
-        }
     }
 
-    fn link(&mut self, value: Link) {
-        match value {
-            Link::Direct(to) => {
-                self.push(Instruction::Jump(to as u64));
-            }
-            Link::Branch {
-                condition,
-                true_,
-                false_,
-            } => {
-                let condition = condition.visit(self);
-                self.push(Instruction::JumpIf {
-                    condition,
-                    to: true_ as u64,
-                });
-                self.push(Instruction::Jump(false_ as u64));
+    fn get(&mut self, identifier: Identifier) -> u64 {
+        let id = self.allocate();
+        self.types.insert(id, self.variables[&identifier].clone());
+        self.push(Instruction::Get { to: id, identifier });
+        self.loaded.insert(identifier, id);
+        id
+    }
+
+    fn set(&mut self, identifier: Identifier, from: u64) {
+        self.push(Instruction::Set { identifier, from });
+        self.loaded.insert(identifier, from);
+    }
+
+    fn call(&mut self, label: &str, value: u64) {
+        self.push(Instruction::Call {
+            label: Identifier::from(label),
+            value,
+        });
+    }
+
+    fn operation(&mut self, kind: BinaryKind, left: u64, right: u64) -> u64 {
+        let to = self.allocate();
+        let type_ = match kind {
+            BinaryKind::Greater | BinaryKind::Less | BinaryKind::Equal => Type::Boolean,
+            _ => Type::I64,
+        };
+        self.types.insert(to, type_);
+        let instruction = match kind {
+            BinaryKind::Add => Instruction::Add { to, left, right },
+            BinaryKind::Subtract => Instruction::Subtract { to, left, right },
+            BinaryKind::Multiply => Instruction::Multiply { to, left, right },
+            BinaryKind::Divide => Instruction::Divide { to, left, right },
+            BinaryKind::Remainder => Instruction::Remainder { to, left, right },
+            BinaryKind::Power => Instruction::Power { to, left, right },
+            BinaryKind::Greater => Instruction::Greater { to, left, right },
+            BinaryKind::Less => Instruction::Less { to, left, right },
+            BinaryKind::Equal => Instruction::Equal { to, left, right },
+        };
+        self.push(instruction);
+        to
+    }
+
+    /// Evaluates `base ** exponent` at compile time when the exponent is a
+    /// literal and the result does not depend on a pure base, or when both
+    /// are literals.
+    fn fold_power(&mut self, base: &Expression, exponent: &Expression) -> Option<u64> {
+        let exponent = literal(exponent)?;
+        match (literal(base), exponent) {
+            (Some(base), exponent) => {
+                let value = evaluate::power(base, exponent);
+                Some(self.integer(&Integer { value }))
             }
+            (None, 0) if pure(base) => Some(self.integer(&Integer { value: 1 })),
+            (None, 1) => Some(base.visit(self)),
+            (None, _) => None,
         }
     }
 
-    pub fn construct(mut self, source: Vec<Block>) -> Vec<Instruction> {
-        for (id, block) in source.into_iter().enumerate() {
-            self.push(Instruction::Label(id as u64));
-            self.block(block);
+    pub fn construct(mut self, source: &[Statement]) -> Graph {
+        for statement in source {
+            statement.visit(&mut self, &mut ());
         }
-        self.output
+        Graph::new(self.blocks)
     }
 }
 
@@ -70,112 +146,216 @@ impl VisitStatement for Constructor {
     type Output = ();
     type Context = ();
 
-    fn let_(&mut self, node: Let, _context: &mut Self::Context) -> Self::Output {
+    fn let_(&mut self, node: &Let, _context: &mut Self::Context) -> Self::Output {
         let from = node.value.visit(self);
-        self.push(Instruction::Set {
-            identifier: node.identifier.name,
-            from,
-        });
+        // the symbol table only has the type of the last binding
+        self.variables.insert(node.identifier, self.types[&from].clone());
+        self.set(node.identifier, from);
     }
 
-    fn assign(&mut self, node: Assign, _context: &mut Self::Context) -> Self::Output {
-        let from = node.value.visit(self);
-        self.push(Instruction::Set {
-            identifier: node.identifier.name,
-            from,
-        });
+    fn const_(&mut self, _node: &Const, _context: &mut Self::Context) -> Self::Output {
+        // uses are inlined, so there is nothing to run
     }
 
-    fn debug(&mut self, node: Debug, _context: &mut Self::Context) -> Self::Output {
-        let value = node.value.visit(self);
-        self.push(Instruction::Call {
-            label: "debug_i64".into(),
-            value,
-        });
+    fn assign(&mut self, node: &Assign, _context: &mut Self::Context) -> Self::Output {
+        let from = match node.operator {
+            Some(operator) => {
+                // no need to load the target again if this block already did
+                let current = match self.loaded.get(&node.identifier) {
+                    Some(id) => *id,
+                    None => self.get(node.identifier),
+                };
+                let value = node.value.visit(self);
+                self.operation(operator, current, value)
+            }
+            None => node.value.visit(self),
+        };
+        self.set(node.identifier, from);
+    }
+
+    fn if_(&mut self, node: &If<Body>, _context: &mut Self::Context) -> Self::Output {
+        let start = self.current;
+        let mut ends = Vec::new();
+        let last = node.or.len();
+        for (index, branch) in std::iter::once(&node.if_).chain(&node.or).enumerate() {
+            let condition = branch.condition.visit(self);
+            let body = self.block(Origin::Branch(index), self.current);
+            let otherwise = Origin::Otherwise {
+                branch: index,
+                last: index == last,
+            };
+            let next = self.block(otherwise, self.current);
+            self.terminate(Terminator::Branch {
+                condition,
+                true_: body,
+                false_: next,
+            });
+            self.switch(body);
+            self.body(&branch.body);
+            ends.push(self.current);
+            self.switch(next);
+        }
+        if let Some(body) = &node.else_ {
+            self.body(body);
+        }
+        ends.push(self.current);
+
+        let end = self.block(Origin::AfterIf, start);
+        for id in ends {
+            self.blocks[id].terminator = Terminator::Jump(end);
+        }
+        self.switch(end);
+    }
+
+    fn while_(&mut self, node: &While, _context: &mut Self::Context) -> Self::Output {
+        let header = self.block(Origin::Condition, self.current);
+        self.terminate(Terminator::Jump(header));
+        self.switch(header);
+        let condition = node.condition.visit(self);
+        let body = self.block(Origin::Loop, header);
+        self.switch(body);
+        self.body(&node.body);
+        self.terminate(Terminator::Jump(header));
+
+        let end = self.block(Origin::AfterLoop, header);
+        self.blocks[header].terminator = Terminator::Branch {
+            condition,
+            true_: body,
+            false_: end,
+        };
+        self.switch(end);
+    }
+
+    fn debug(&mut self, node: &Debug, _context: &mut Self::Context) -> Self::Output {
+        // every value but the last is followed by a space instead of a newline
+        for (index, value) in node.values.iter().enumerate() {
+            let value = value.visit(self);
+            let last = index + 1 == node.values.len();
+            let prefix = if last { "debug" } else { "write" };
+            self.call(&format!("{prefix}_{}", self.types[&value].suffix()), value);
+            if !last {
+                let space = self.char(&Char { value: b' ' });
+                self.call("write_c", space);
+            }
+        }
     }
 }
 
 impl VisitExpression for Constructor {
     type Output = u64;
 
-    fn integer(&mut self, node: Integer) -> Self::Output {
+    fn integer(&mut self, node: &Integer) -> Self::Output {
         let id = self.allocate();
-        self.output.push(Instruction::Integer {
+        self.types.insert(id, Type::I64);
+        self.push(Instruction::Integer {
             to: id,
-            value: node.value,
+            value: node.value as i128,
         });
         id
     }
 
-    fn identifier(&mut self, node: Identifier) -> Self::Output {
+    fn identifier(&mut self, node: &Identifier) -> Self::Output {
+        if let Some((value, type_)) = self.constants.get(node).cloned() {
+            let id = self.integer(&Integer { value });
+            self.types.insert(id, type_);
+            return id;
+        }
+        self.get(*node)
+    }
+
+    fn boolean(&mut self, node: &Boolean) -> Self::Output {
         let id = self.allocate();
-        self.push(Instruction::Get {
+        self.types.insert(id, Type::Boolean);
+        self.push(Instruction::Integer {
             to: id,
-            identifier: node.name,
+            value: node.value as i128,
         });
         id
     }
 
-    fn boolean(&mut self, node: Boolean) -> Self::Output {
+    fn string(&mut self, node: &Str) -> Self::Output {
         let id = self.allocate();
-        self.output.push(Instruction::Integer {
+        self.types.insert(id, Type::String);
+        self.push(Instruction::String {
             to: id,
-            value: node.value as i128,
+            value: node.value.clone(),
         });
         id
     }
 
-    fn string(&mut self, node: Str) -> Self::Output {
+    fn char(&mut self, node: &Char) -> Self::Output {
         let id = self.allocate();
-        self.push(Instruction::String {
+        self.types.insert(id, Type::Char);
+        self.push(Instruction::Integer {
             to: id,
-            value: node.value,
+            value: node.value as i128,
         });
         id
     }
 
-    fn binary(&mut self, node: Binary) -> Self::Output {
+    fn binary(&mut self, node: &Binary) -> Self::Output {
+        if node.kind == BinaryKind::Power
+            && let Some(id) = self.fold_power(&node.left, &node.right)
+        {
+            return id;
+        }
         let left = node.left.visit(self);
         let right = node.right.visit(self);
+        self.operation(node.kind, left, right)
+    }
+
+    fn unary(&mut self, node: &Unary) -> Self::Output {
+        let inner = node.inner.visit(self);
+        let zero = self.allocate();
+        self.push(Instruction::Integer { to: zero, value: 0 });
         let id = self.allocate();
+        let type_ = match node.kind {
+            UnaryKind::Negate => Type::I64,
+            UnaryKind::Not => Type::Boolean,
+        };
+        self.types.insert(id, type_);
         let instruction = match node.kind {
-            BinaryKind::Add => Instruction::Add {
-                to: id,
-                left,
-                right,
-            },
-            BinaryKind::Subtract => Instruction::Subtract {
-                to: id,
-                left,
-                right,
-            },
-            BinaryKind::Multiply => Instruction::Multiply {
-                to: id,
-                left,
-                right,
-            },
-            BinaryKind::Divide => Instruction::Divide {
-                to: id,
-                left,
-                right,
-            },
-            BinaryKind::Greater => Instruction::Greater {
+            UnaryKind::Negate => Instruction::Subtract {
                 to: id,
-                left,
-                right,
+                left: zero,
+                right: inner,
             },
-            BinaryKind::Less => Instruction::Less {
+            UnaryKind::Not => Instruction::Equal {
                 to: id,
-                left,
-                right,
-            },
-            BinaryKind::Equal => Instruction::Equal {
-                to: id,
-                left,
-                right,
+                left: inner,
+                right: zero,
             },
         };
         self.push(instruction);
         id
     }
 }
+
+fn literal(expression: &Expression) -> Option<i64> {
+    match expression {
+        Expression::Integer(node) => Some(node.value),
+        Expression::Unary(node) if node.kind == UnaryKind::Negate => {
+            literal(&node.inner).map(i64::wrapping_neg)
+        }
+        _ => None,
+    }
+}
+
+/// Whether evaluating `expression` cannot stop the program, so skipping it
+/// changes nothing. Division traps when the divisor is zero.
+fn pure(expression: &Expression) -> bool {
+    match expression {
+        Expression::Integer(_)
+        | Expression::Identifier(_)
+        | Expression::Boolean(_)
+        | Expression::String(_)
+        | Expression::Char(_) => true,
+        Expression::Binary(node) => {
+            !matches!(node.kind, BinaryKind::Divide | BinaryKind::Remainder)
+                && pure(&node.left)
+                && pure(&node.right)
+        }
+        Expression::Unary(node) => pure(&node.inner),
+        _ => false,
+    }
+}
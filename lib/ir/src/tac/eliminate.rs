@@ -0,0 +1,84 @@
+use std::collections::HashSet;
+
+use super::Instruction;
+use crate::cfg::{Graph, Origin};
+
+/// Removes results that are never read and stores to variables that are
+/// never loaded, until nothing else can go.
+fn sweep(source: &mut Graph) {
+    loop {
+        let mut read: HashSet<u64> = HashSet::new();
+        let mut loaded = HashSet::new();
+        for block in &source.blocks {
+            for instruction in &block.body {
+                read.extend(instruction.operands());
+                if let Instruction::Get { identifier, .. } = instruction {
+                    loaded.insert(*identifier);
+                }
+            }
+            read.extend(block.terminator.operands());
+        }
+        let mut changed = false;
+        for block in source.blocks.iter_mut() {
+            let length = block.body.len();
+            block.body.retain(|instruction| match instruction {
+                Instruction::Set { identifier, .. } => loaded.contains(identifier),
+                instruction => instruction.result().is_none_or(|to| read.contains(&to)),
+            });
+            changed |= block.body.len() != length;
+        }
+        if !changed {
+            return;
+        }
+    }
+}
+
+/// Whether a block cannot be reached although its statement can, which is
+/// where unreachable user code is cut off.
+fn cut_off(source: &Graph, reachable: &[bool], id: usize) -> bool {
+    let Some((_, from)) = source.blocks[id].origin else {
+        return false;
+    };
+    !reachable[id] && reachable[from]
+}
+
+/// Whether a cut off block holds code, or leads to code that is not cut off
+/// in turn.
+fn holds_code(source: &Graph, reachable: &[bool], id: usize) -> bool {
+    let mut seen = HashSet::from([id]);
+    let mut stack = vec![id];
+    while let Some(id) = stack.pop() {
+        if !source.blocks[id].body.is_empty() {
+            return true;
+        }
+        for successor in &source.blocks[id].successors {
+            if !reachable[*successor]
+                && !cut_off(source, reachable, *successor)
+                && seen.insert(*successor)
+            {
+                stack.push(*successor);
+            }
+        }
+    }
+    false
+}
+
+/// Where unreachable user code is cut off. Code nested in it or following it
+/// is not reported again.
+fn unreachable(source: &Graph, reachable: &[bool]) -> Vec<Origin> {
+    (0..source.blocks.len())
+        .filter(|id| cut_off(source, reachable, *id) && holds_code(source, reachable, *id))
+        .filter_map(|id| source.blocks[id].origin.map(|(origin, _)| origin))
+        .collect()
+}
+
+/// Deletes blocks that cannot be reached from block 0 and code whose result
+/// is never used. Also returns where the deleted user code came from, so the
+/// driver can warn about it.
+pub fn eliminate(mut source: Graph) -> (Graph, Vec<Origin>) {
+    let reachable = source.reachable();
+    let unreachable = unreachable(&source, &reachable);
+    source.retain(&reachable);
+    sweep(&mut source);
+    (source, unreachable)
+}
@@ -0,0 +1,30 @@
+/// Integer power with the semantics of `power_i64` from `sl`: the result
+/// wraps around on overflow and a negative exponent truncates towards zero.
+pub fn power(mut base: i64, mut exponent: i64) -> i64 {
+    if exponent < 0 {
+        return match base {
+            1 => 1,
+            -1 if exponent % 2 == 0 => 1,
+            -1 => -1,
+            _ => 0,
+        };
+    }
+    let mut output: i64 = 1;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            output = output.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    output
+}
+
+/// Like [`power`], but returns `None` when the result does not fit in `i64`.
+pub fn checked_power(base: i64, exponent: i64) -> Option<i64> {
+    match u32::try_from(exponent) {
+        Ok(exponent) => base.checked_pow(exponent),
+        Err(_) if exponent < 0 || matches!(base, -1..=1) => Some(power(base, exponent)),
+        Err(_) => None,
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::{HashMap, HashSet};
+
+use mellow_parse::Identifier;
+
+use super::{Instruction, evaluate};
+use crate::{
+    SymbolTable,
+    cfg::{Graph, Terminator},
+};
+
+struct Folder {
+    // immutable variables that are set exactly once
+    immutable: HashSet<Identifier>,
+    variables: HashMap<Identifier, i64>,
+    temporaries: HashMap<u64, i64>,
+}
+
+impl Folder {
+    fn new(source: &Graph, table: &SymbolTable) -> Self {
+        let mut sets: HashMap<Identifier, usize> = HashMap::new();
+        for instruction in source.blocks.iter().flat_map(|block| &block.body) {
+            if let Instruction::Set { identifier, .. } = instruction {
+                *sets.entry(*identifier).or_default() += 1;
+            }
+        }
+        let immutable = table
+            .variables()
+            .filter(|(identifier, meta)| !meta.mutable && sets.get(identifier) == Some(&1))
+            .map(|(identifier, _)| *identifier)
+            .collect();
+        Self {
+            immutable,
+            variables: HashMap::new(),
+            temporaries: HashMap::new(),
+        }
+    }
+
+    /// Returns whether anything changed.
+    fn pass(&mut self, source: &mut Graph) -> bool {
+        let mut changed = false;
+        for block in source.blocks.iter_mut() {
+            for instruction in block.body.iter_mut() {
+                let folded = self.instruction(instruction.clone());
+                if folded != *instruction {
+                    *instruction = folded;
+                    changed = true;
+                }
+            }
+            if let Terminator::Branch {
+                condition,
+                true_,
+                false_,
+            } = block.terminator
+                && let Some(value) = self.temporaries.get(&condition)
+            {
+                let to = if *value != 0 { true_ } else { false_ };
+                block.terminator = Terminator::Jump(to);
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn instruction(&mut self, instruction: Instruction) -> Instruction {
+        let instruction = match instruction {
+            Instruction::Get { to, identifier } => match self.variables.get(&identifier) {
+                Some(value) => Instruction::Integer {
+                    to,
+                    value: *value as i128,
+                },
+                None => instruction,
+            },
+            Instruction::Set { identifier, from } => {
+                if self.immutable.contains(&identifier)
+                    && let Some(value) = self.temporaries.get(&from)
+                {
+                    self.variables.insert(identifier, *value);
+                }
+                instruction
+            }
+            instruction => self.operation(instruction),
+        };
+        if let Instruction::Integer { to, value } = instruction {
+            self.temporaries.insert(to, value as i64);
+        }
+        instruction
+    }
+
+    fn operation(&self, instruction: Instruction) -> Instruction {
+        let (to, left, right) = match instruction {
+            Instruction::Add { to, left, right }
+            | Instruction::Subtract { to, left, right }
+            | Instruction::Multiply { to, left, right }
+            | Instruction::Divide { to, left, right }
+            | Instruction::Remainder { to, left, right }
+            | Instruction::Power { to, left, right }
+            | Instruction::Greater { to, left, right }
+            | Instruction::Less { to, left, right }
+            | Instruction::Equal { to, left, right } => (to, left, right),
+            _ => return instruction,
+        };
+        let (Some(&left), Some(&right)) =
+            (self.temporaries.get(&left), self.temporaries.get(&right))
+        else {
+            return instruction;
+        };
+        let value = match instruction {
+            Instruction::Add { .. } => left.wrapping_add(right),
+            Instruction::Subtract { .. } => left.wrapping_sub(right),
+            Instruction::Multiply { .. } => left.wrapping_mul(right),
+            // division by zero and `i64::MIN / -1` trap at runtime, so keep them
+            Instruction::Divide { .. } => match left.checked_div(right) {
+                Some(value) => value,
+                None => return instruction,
+            },
+            Instruction::Remainder { .. } => match left.checked_rem(right) {
+                Some(value) => value,
+                None => return instruction,
+            },
+            Instruction::Power { .. } => evaluate::power(left, right),
+            Instruction::Greater { .. } => (left > right) as i64,
+            Instruction::Less { .. } => (left < right) as i64,
+            Instruction::Equal { .. } => (left == right) as i64,
+            _ => unreachable!(),
+        };
+        Instruction::Integer {
+            to,
+            value: value as i128,
+        }
+    }
+}
+
+/// Folds arithmetic and comparisons on known constants, propagates them
+/// through immutable variables and resolves constant branches.
+pub fn fold(mut source: Graph, table: &SymbolTable) -> Graph {
+    let mut folder = Folder::new(&source, table);
+    // a `Get` can come before its `Set` in block order, so repeat until stable
+    while folder.pass(&mut source) {}
+    source.link();
+    source
+}
@@ -1,22 +1,90 @@
 use std::fmt::{self, Display, Formatter};
 
-#[derive(Debug)]
+use mellow_parse::Identifier;
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     Label(u64),
     Integer { to: u64, value: i128 },
-    Get { to: u64, identifier: String },
-    Set { identifier: String, from: u64 },
+    Get { to: u64, identifier: Identifier },
+    Set { identifier: Identifier, from: u64 },
     String { to: u64, value: String },
     Add { to: u64, left: u64, right: u64 },
     Subtract { to: u64, left: u64, right: u64 },
     Multiply { to: u64, left: u64, right: u64 },
     Divide { to: u64, left: u64, right: u64 },
+    Remainder { to: u64, left: u64, right: u64 },
+    Power { to: u64, left: u64, right: u64 },
     Greater { to: u64, left: u64, right: u64 },
     Less { to: u64, left: u64, right: u64 },
     Equal { to: u64, left: u64, right: u64 },
     Jump(u64),
     JumpIf { condition: u64, to: u64 },
-    Call { label: String, value: u64 },
+    Call { label: Identifier, value: u64 },
+    /// Only exists in SSA form: the value of `identifier` coming from each
+    /// `(label, temporary)` predecessor.
+    Phi { to: u64, identifier: Identifier, from: Vec<(u64, u64)> },
+}
+
+impl Instruction {
+    /// The temporary this instruction defines, if any.
+    pub fn result(&self) -> Option<u64> {
+        match self {
+            Self::Integer { to, .. }
+            | Self::Get { to, .. }
+            | Self::String { to, .. }
+            | Self::Add { to, .. }
+            | Self::Subtract { to, .. }
+            | Self::Multiply { to, .. }
+            | Self::Divide { to, .. }
+            | Self::Remainder { to, .. }
+            | Self::Power { to, .. }
+            | Self::Greater { to, .. }
+            | Self::Less { to, .. }
+            | Self::Equal { to, .. }
+            | Self::Phi { to, .. } => Some(*to),
+            _ => None,
+        }
+    }
+
+    /// The temporaries this instruction reads.
+    pub fn operands(&self) -> Vec<u64> {
+        match self {
+            Self::Add { left, right, .. }
+            | Self::Subtract { left, right, .. }
+            | Self::Multiply { left, right, .. }
+            | Self::Divide { left, right, .. }
+            | Self::Remainder { left, right, .. }
+            | Self::Power { left, right, .. }
+            | Self::Greater { left, right, .. }
+            | Self::Less { left, right, .. }
+            | Self::Equal { left, right, .. } => vec![*left, *right],
+            Self::Set { from, .. } => vec![*from],
+            Self::JumpIf { condition, .. } => vec![*condition],
+            Self::Call { value, .. } => vec![*value],
+            Self::Phi { from, .. } => from.iter().map(|(_, id)| *id).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut u64> {
+        match self {
+            Self::Add { left, right, .. }
+            | Self::Subtract { left, right, .. }
+            | Self::Multiply { left, right, .. }
+            | Self::Divide { left, right, .. }
+            | Self::Remainder { left, right, .. }
+            | Self::Power { left, right, .. }
+            | Self::Greater { left, right, .. }
+            | Self::Less { left, right, .. }
+            | Self::Equal { left, right, .. } => vec![left, right],
+            Self::Set { from, .. } => vec![from],
+            Self::JumpIf { condition, .. } => vec![condition],
+            Self::Call { value, .. } => vec![value],
+            Self::Phi { from, .. } => from.iter_mut().map(|(_, id)| id).collect(),
+            _ => Vec::new(),
+        }
+    }
 }
 
 impl Display for Instruction {
@@ -49,6 +117,12 @@ impl Display for Instruction {
             Self::Divide { to, left, right } => {
                 write!(f, "#{to} div #{left} #{right}")
             }
+            Self::Remainder { to, left, right } => {
+                write!(f, "#{to} rem #{left} #{right}")
+            }
+            Self::Power { to, left, right } => {
+                write!(f, "#{to} pow #{left} #{right}")
+            }
             Self::Greater { to, left, right } => {
                 write!(f, "#{to} gt #{left} #{right}")
             }
@@ -67,6 +141,17 @@ impl Display for Instruction {
             Self::Call { label, value } => {
                 write!(f, "call {label} #{value}")
             }
+            Self::Phi {
+                to,
+                identifier,
+                from,
+            } => {
+                write!(f, "#{to} phi ${identifier}")?;
+                for (label, id) in from {
+                    write!(f, " @{label} #{id}")?;
+                }
+                Ok(())
+            }
         }
     }
 }
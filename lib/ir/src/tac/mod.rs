@@ -1,11 +1,19 @@
 mod constructor;
+mod eliminate;
+pub mod evaluate;
+mod fold;
 mod instruction;
+mod number;
 use constructor::Constructor;
+pub use eliminate::eliminate;
+pub use fold::fold;
 pub use instruction::Instruction;
+use mellow_parse::Statement;
+pub use number::number;
 
-use crate::cfg::Block;
+use crate::{SymbolTable, cfg::Graph};
 
-pub fn construct(source: Vec<Block>) -> Vec<Instruction> {
-    let constructor = Constructor::new();
+pub fn construct(source: &[Statement], table: &SymbolTable) -> Graph {
+    let constructor = Constructor::new(table);
     constructor.construct(source)
 }
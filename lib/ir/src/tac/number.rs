@@ -0,0 +1,74 @@
+use std::{
+    collections::HashMap,
+    mem::{Discriminant, discriminant},
+};
+
+use mellow_parse::Identifier;
+
+use super::Instruction;
+use crate::cfg::Graph;
+
+#[derive(PartialEq, Eq, Hash)]
+enum Value {
+    Integer(i128),
+    Load(Identifier),
+    Operation(Discriminant<Instruction>, u64, u64),
+}
+
+fn rename(operands: Vec<&mut u64>, replaced: &HashMap<u64, u64>) {
+    for id in operands {
+        if let Some(replacement) = replaced.get(id) {
+            *id = *replacement;
+        }
+    }
+}
+
+fn value(instruction: &Instruction) -> Option<(u64, Value)> {
+    let (to, left, right) = match *instruction {
+        Instruction::Integer { to, value } => return Some((to, Value::Integer(value))),
+        Instruction::Get { to, identifier } => return Some((to, Value::Load(identifier))),
+        // the order of operands does not matter for these
+        Instruction::Add { to, left, right }
+        | Instruction::Multiply { to, left, right }
+        | Instruction::Equal { to, left, right } => (to, left.min(right), left.max(right)),
+        Instruction::Subtract { to, left, right }
+        | Instruction::Divide { to, left, right }
+        | Instruction::Remainder { to, left, right }
+        | Instruction::Power { to, left, right }
+        | Instruction::Greater { to, left, right }
+        | Instruction::Less { to, left, right } => (to, left, right),
+        _ => return None,
+    };
+    Some((to, Value::Operation(discriminant(instruction), left, right)))
+}
+
+/// Local value numbering: within a block, a pure computation that was already
+/// done is replaced with the temporary that holds its result.
+pub fn number(mut source: Graph) -> Graph {
+    let mut replaced: HashMap<u64, u64> = HashMap::new();
+    for block in source.blocks.iter_mut() {
+        let mut values: HashMap<Value, u64> = HashMap::new();
+        let body = std::mem::take(&mut block.body);
+        for mut instruction in body {
+            rename(instruction.operands_mut(), &replaced);
+            match &instruction {
+                Instruction::Set { identifier, from } => {
+                    // the variable now holds `from`, whatever it held before
+                    values.insert(Value::Load(*identifier), *from);
+                }
+                instruction => {
+                    if let Some((to, value)) = value(instruction) {
+                        if let Some(existing) = values.get(&value) {
+                            replaced.insert(to, *existing);
+                            continue;
+                        }
+                        values.insert(value, to);
+                    }
+                }
+            }
+            block.body.push(instruction);
+        }
+        rename(block.terminator.operands_mut(), &replaced);
+    }
+    source
+}
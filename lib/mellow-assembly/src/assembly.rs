@@ -1,72 +1,154 @@
 use std::fmt::{self, Display, Formatter};
 
-use crate::data::Data;
+use mellow_parse::Identifier;
+
+use crate::{
+    data::Data,
+    syntax::{Spelled, Syntax},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Assembly {
     Label(u64),
     Mov(Data, Data),
     Cmp(Data, Data),
+    Test(Data, Data),
     Add(Data, Data),
     Sub(Data, Data),
+    Xor(Data, Data),
     Imul(Data, Data),
     Idiv(Data),
+    Lea(Data, Data),
+    Shl(Data, Data),
+    Shr(Data, Data),
+    Sar(Data, Data),
     Cqo,
     Sete(Data),
     Setg(Data),
     Setl(Data),
     Jmp(u64),
     Je(u64),
-    Call(String),
+    Jne(u64),
+    Jg(u64),
+    Jl(u64),
+    Jge(u64),
+    Jle(u64),
+    Call(Identifier),
+    Push(Data),
+    Pop(Data),
+    Syscall,
+    /// A null-terminated string constant.
+    String(usize, String),
     Empty,
 }
 
 impl Display for Assembly {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Self::Label(id) => {
+        Spelled(self, Syntax::Nasm).fmt(f)
+    }
+}
+
+impl Display for Spelled<'_, Assembly> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Spelled(assembly, syntax) = *self;
+        let data = |data| Spelled(data, syntax);
+        match assembly {
+            Assembly::Label(id) => {
                 write!(f, "_{id}:")
             }
-            Self::Mov(to, from) => {
-                write!(f, "mov {to}, {from}")
+            Assembly::Mov(to, from) => {
+                write!(f, "mov {}, {}", data(to), data(from))
+            }
+            Assembly::Cmp(first, second) => {
+                write!(f, "cmp {}, {}", data(first), data(second))
+            }
+            Assembly::Test(first, second) => {
+                write!(f, "test {}, {}", data(first), data(second))
+            }
+            Assembly::Xor(to, value) => {
+                write!(f, "xor {}, {}", data(to), data(value))
+            }
+            Assembly::Add(to, value) => {
+                write!(f, "add {}, {}", data(to), data(value))
+            }
+            Assembly::Sub(to, value) => {
+                write!(f, "sub {}, {}", data(to), data(value))
             }
-            Self::Cmp(first, second) => {
-                write!(f, "cmp {first}, {second}")
+            Assembly::Imul(to, value) => {
+                write!(f, "imul {}, {}", data(to), data(value))
             }
-            Self::Add(to, value) => {
-                write!(f, "add {to}, {value}")
+            Assembly::Idiv(operand) => {
+                write!(f, "idiv {}", data(operand))
             }
-            Self::Sub(to, value) => {
-                write!(f, "sub {to}, {value}")
+            Assembly::Lea(to, address) => {
+                write!(f, "lea {}, {}", data(to), data(address))
             }
-            Self::Imul(to, value) => {
-                write!(f, "imul {to}, {value}")
+            Assembly::Shl(to, count) => {
+                write!(f, "shl {}, {}", data(to), data(count))
             }
-            Self::Idiv(data) => {
-                write!(f, "idiv {data}")
+            Assembly::Shr(to, count) => {
+                write!(f, "shr {}, {}", data(to), data(count))
             }
-            Self::Cqo => {
+            Assembly::Sar(to, count) => {
+                write!(f, "sar {}, {}", data(to), data(count))
+            }
+            Assembly::Cqo => {
                 write!(f, "cqo")
             }
-            Self::Sete(register) => {
-                write!(f, "sete {register}")
+            Assembly::Sete(register) => {
+                write!(f, "sete {}", data(register))
             }
-            Self::Setg(register) => {
-                write!(f, "setg {register}")
+            Assembly::Setg(register) => {
+                write!(f, "setg {}", data(register))
             }
-            Self::Setl(register) => {
-                write!(f, "setl {register}")
+            Assembly::Setl(register) => {
+                write!(f, "setl {}", data(register))
             }
-            Self::Jmp(label) => {
+            Assembly::Jmp(label) => {
                 write!(f, "jmp _{label}")
             }
-            Self::Je(label) => {
+            Assembly::Je(label) => {
                 write!(f, "je _{label}")
             }
-            Self::Call(label) => {
+            Assembly::Jne(label) => {
+                write!(f, "jne _{label}")
+            }
+            Assembly::Jg(label) => {
+                write!(f, "jg _{label}")
+            }
+            Assembly::Jl(label) => {
+                write!(f, "jl _{label}")
+            }
+            Assembly::Jge(label) => {
+                write!(f, "jge _{label}")
+            }
+            Assembly::Jle(label) => {
+                write!(f, "jle _{label}")
+            }
+            Assembly::Call(label) => {
                 write!(f, "call {label}")
             }
-            Self::Empty => Ok(()),
+            Assembly::Push(operand) => {
+                write!(f, "push {}", data(operand))
+            }
+            Assembly::Pop(operand) => {
+                write!(f, "pop {}", data(operand))
+            }
+            Assembly::Syscall => {
+                write!(f, "syscall")
+            }
+            Assembly::String(id, value) => {
+                let directive = match syntax {
+                    Syntax::Nasm => "db",
+                    Syntax::Gas => ".byte",
+                };
+                write!(f, "_s{id}: {directive} ")?;
+                for byte in value.bytes() {
+                    write!(f, "{byte}, ")?;
+                }
+                write!(f, "0")
+            }
+            Assembly::Empty => Ok(()),
         }
     }
 }
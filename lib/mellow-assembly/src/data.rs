@@ -1,22 +1,65 @@
 use std::fmt::{self, Display, Formatter};
 
-use crate::Register;
+use crate::{
+    Register,
+    syntax::{Spelled, Syntax},
+};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Data {
     Register(Register),
-    Stack(u8),
+    Stack(u32),
     Integer(i128),
-    Identifier(String), // NOTE: Temporary, will be removed
+    /// Address of a string constant.
+    String(usize),
+    /// `base + index * scale + displacement`, as taken by `lea`.
+    Address {
+        base: Option<Register>,
+        index: Option<(Register, u8)>,
+        displacement: i128,
+    },
 }
 
 impl Display for Data {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        match self {
-            Self::Register(register) => write!(f, "{register}"),
-            Self::Stack(offset) => write!(f, "[rsp - {offset}]"),
-            Self::Integer(value) => write!(f, "{value}"),
-            Self::Identifier(identifier) => write!(f, "qword [{identifier}]"),
+        Spelled(self, Syntax::Nasm).fmt(f)
+    }
+}
+
+impl Display for Spelled<'_, Data> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Spelled(data, syntax) = *self;
+        match (data, syntax) {
+            (Data::Register(register), _) => write!(f, "{register}"),
+            (Data::Stack(offset), Syntax::Nasm) => write!(f, "qword [rbp - {offset}]"),
+            (Data::Stack(offset), Syntax::Gas) => write!(f, "qword ptr [rbp - {offset}]"),
+            (Data::Integer(value), _) => write!(f, "{value}"),
+            (Data::String(id), Syntax::Nasm) => write!(f, "_s{id}"),
+            // a bare symbol would be read from memory
+            (Data::String(id), Syntax::Gas) => write!(f, "offset _s{id}"),
+            (
+                Data::Address {
+                    base,
+                    index,
+                    displacement,
+                },
+                _,
+            ) => {
+                let mut parts: Vec<_> = base.iter().map(ToString::to_string).collect();
+                parts.extend(
+                    index
+                        .iter()
+                        .map(|(index, scale)| format!("{index}*{scale}")),
+                );
+                write!(f, "[{}", parts.join(" + "))?;
+                match displacement {
+                    0 if !parts.is_empty() => {}
+                    _ if parts.is_empty() => write!(f, "{displacement}")?,
+                    1.. => write!(f, " + {displacement}")?,
+                    _ => write!(f, " - {}", -displacement)?,
+                }
+                write!(f, "]")
+            }
         }
     }
 }
@@ -0,0 +1,307 @@
+use std::collections::HashMap;
+
+use mellow_parse::Identifier;
+
+use crate::{
+    assembly::Assembly,
+    encode::{Code, Target},
+};
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_SECTION: u8 = 3;
+
+const R_X86_64_PC32: u64 = 2;
+const R_X86_64_PLT32: u64 = 4;
+
+// section indices, in the order `write` lays them out
+const TEXT: u16 = 1;
+const RODATA: u16 = 2;
+const SYMTAB: u32 = 4;
+const STRTAB: u32 = 5;
+
+struct Section {
+    name: &'static str,
+    type_: u32,
+    flags: u64,
+    data: Vec<u8>,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+impl Section {
+    fn new(name: &'static str, type_: u32, flags: u64, data: Vec<u8>, align: u64) -> Self {
+        Self {
+            name,
+            type_,
+            flags,
+            data,
+            link: 0,
+            info: 0,
+            align,
+            entry_size: 0,
+        }
+    }
+}
+
+/// A string table, which starts with an empty name.
+struct Strings {
+    data: Vec<u8>,
+}
+
+impl Strings {
+    fn new() -> Self {
+        Self { data: vec![0] }
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(name.as_bytes());
+        self.data.push(0);
+        offset
+    }
+}
+
+fn symbol(output: &mut Vec<u8>, name: u32, info: u8, section: u16, value: u64) {
+    output.extend_from_slice(&name.to_le_bytes());
+    output.push(info);
+    output.push(0);
+    output.extend_from_slice(&section.to_le_bytes());
+    output.extend_from_slice(&value.to_le_bytes());
+    output.extend_from_slice(&0u64.to_le_bytes());
+}
+
+fn align(output: &mut Vec<u8>, align: u64) {
+    while !(output.len() as u64).is_multiple_of(align) {
+        output.push(0);
+    }
+}
+
+/// The ELF64 file header. Program headers, if any, come right after it.
+pub fn header(
+    type_: u16,
+    entry: u64,
+    segments: u16,
+    section_headers: u64,
+    sections: u16,
+    names: u16,
+) -> Vec<u8> {
+    let program_headers: u64 = if segments > 0 { 64 } else { 0 };
+    let mut header = Vec::new();
+    header.extend_from_slice(b"\x7fELF");
+    // 64-bit, little endian, version 1, System V
+    header.extend_from_slice(&[2, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+    header.extend_from_slice(&type_.to_le_bytes());
+    header.extend_from_slice(&62u16.to_le_bytes()); // x86-64
+    header.extend_from_slice(&1u32.to_le_bytes());
+    header.extend_from_slice(&entry.to_le_bytes());
+    header.extend_from_slice(&program_headers.to_le_bytes());
+    header.extend_from_slice(&section_headers.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes()); // flags
+    header.extend_from_slice(&64u16.to_le_bytes());
+    header.extend_from_slice(&56u16.to_le_bytes());
+    header.extend_from_slice(&segments.to_le_bytes());
+    header.extend_from_slice(&64u16.to_le_bytes());
+    header.extend_from_slice(&sections.to_le_bytes());
+    header.extend_from_slice(&names.to_le_bytes());
+    header
+}
+
+/// Lays `code` and the string constants in `rodata` out as an ELF64
+/// relocatable object. `_start` is the start of `.text`, and every called
+/// function is an undefined global for the linker to find.
+pub fn write(code: Code, rodata: &[Assembly]) -> Vec<u8> {
+    let mut constants = Vec::new();
+    let mut offsets = HashMap::new();
+    for constant in rodata {
+        if let Assembly::String(id, value) = constant {
+            offsets.insert(*id, constants.len() as i64);
+            constants.extend_from_slice(value.as_bytes());
+            constants.push(0);
+        }
+    }
+
+    let mut names = Strings::new();
+    let mut symbols = Vec::new();
+    symbol(&mut symbols, 0, 0, 0, 0);
+    symbol(&mut symbols, 0, STB_LOCAL << 4 | STT_SECTION, TEXT, 0);
+    symbol(&mut symbols, 0, STB_LOCAL << 4 | STT_SECTION, RODATA, 0);
+    let first_global = 3;
+    let rodata_symbol = 2;
+    let start = names.add("_start");
+    symbol(&mut symbols, start, STB_GLOBAL << 4 | STT_NOTYPE, TEXT, 0);
+
+    let mut externs: HashMap<Identifier, u64> = HashMap::new();
+    let mut relocations = Vec::new();
+    for relocation in &code.relocations {
+        let (symbol_index, type_, addend) = match &relocation.target {
+            Target::Function(label) => {
+                let count = externs.len() as u64;
+                let index = *externs.entry(*label).or_insert_with(|| {
+                    let name = names.add(&label.to_string());
+                    symbol(&mut symbols, name, STB_GLOBAL << 4 | STT_NOTYPE, 0, 0);
+                    first_global + 1 + count
+                });
+                (index, R_X86_64_PLT32, -4)
+            }
+            Target::String(id) => (rodata_symbol, R_X86_64_PC32, offsets[id] - 4),
+        };
+        relocations.extend_from_slice(&(relocation.offset as u64).to_le_bytes());
+        relocations.extend_from_slice(&(symbol_index << 32 | type_).to_le_bytes());
+        relocations.extend_from_slice(&addend.to_le_bytes());
+    }
+
+    let mut rela = Section::new(".rela.text", SHT_RELA, SHF_INFO_LINK, relocations, 8);
+    rela.link = SYMTAB;
+    rela.info = TEXT as u32;
+    rela.entry_size = 24;
+    let mut symtab = Section::new(".symtab", SHT_SYMTAB, 0, symbols, 8);
+    symtab.link = STRTAB;
+    symtab.info = first_global as u32;
+    symtab.entry_size = 24;
+    let mut sections = vec![
+        Section::new("", 0, 0, Vec::new(), 0),
+        Section::new(
+            ".text",
+            SHT_PROGBITS,
+            SHF_ALLOC | SHF_EXECINSTR,
+            code.bytes,
+            16,
+        ),
+        Section::new(".rodata", SHT_PROGBITS, SHF_ALLOC, constants, 1),
+        rela,
+        symtab,
+        Section::new(".strtab", SHT_STRTAB, 0, names.data, 1),
+        Section::new(".shstrtab", SHT_STRTAB, 0, Vec::new(), 1),
+        // marks the stack as not executable
+        Section::new(".note.GNU-stack", SHT_PROGBITS, 0, Vec::new(), 1),
+    ];
+    let mut section_names = Strings::new();
+    let name_offsets: Vec<_> = sections
+        .iter()
+        .map(|section| match section.name {
+            "" => 0,
+            name => section_names.add(name),
+        })
+        .collect();
+    let shstrtab = sections.len() - 2;
+    sections[shstrtab].data = section_names.data;
+
+    let mut output = vec![0; 64];
+    let mut offsets = Vec::new();
+    for section in &sections {
+        align(&mut output, section.align.max(1));
+        offsets.push(output.len() as u64);
+        output.extend_from_slice(&section.data);
+    }
+    align(&mut output, 8);
+    let header_offset = output.len() as u64;
+    for ((section, name), offset) in sections.iter().zip(name_offsets).zip(offsets) {
+        output.extend_from_slice(&name.to_le_bytes());
+        output.extend_from_slice(&section.type_.to_le_bytes());
+        output.extend_from_slice(&section.flags.to_le_bytes());
+        output.extend_from_slice(&0u64.to_le_bytes());
+        output.extend_from_slice(&(if section.type_ == 0 { 0 } else { offset }).to_le_bytes());
+        output.extend_from_slice(&(section.data.len() as u64).to_le_bytes());
+        output.extend_from_slice(&section.link.to_le_bytes());
+        output.extend_from_slice(&section.info.to_le_bytes());
+        output.extend_from_slice(&section.align.to_le_bytes());
+        output.extend_from_slice(&section.entry_size.to_le_bytes());
+    }
+
+    let count = sections.len() as u16;
+    let header = header(1, 0, 0, header_offset, count, shstrtab as u16);
+    output[..64].copy_from_slice(&header);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode::encode;
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// The name, type, offset and size of every section.
+    fn sections(object: &[u8]) -> Vec<(String, u32, usize, usize)> {
+        let headers = u64_at(object, 0x28) as usize;
+        let count = u16_at(object, 0x3c) as usize;
+        let header = |index: usize| &object[headers + index * 64..headers + (index + 1) * 64];
+        let names = header(u16_at(object, 0x3e) as usize);
+        let names = &object[u64_at(names, 24) as usize..];
+        (0..count)
+            .map(|index| {
+                let header = header(index);
+                let name = u32::from_le_bytes(header[..4].try_into().unwrap()) as usize;
+                let end = names[name..].iter().position(|byte| *byte == 0).unwrap();
+                (
+                    String::from_utf8(names[name..name + end].to_vec()).unwrap(),
+                    u32::from_le_bytes(header[4..8].try_into().unwrap()),
+                    u64_at(header, 24) as usize,
+                    u64_at(header, 32) as usize,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn relocatable_object() {
+        let text = [
+            Assembly::Call(Identifier::from("debug_s")),
+            Assembly::Syscall,
+        ];
+        let rodata = [Assembly::String(0, "hi".into())];
+        let object = write(encode(&text), &rodata);
+
+        assert_eq!(&object[..4], b"\x7fELF");
+        assert_eq!(u16_at(&object, 16), 1);
+        assert_eq!(u16_at(&object, 18), 62);
+        let sections = sections(&object);
+        let names: Vec<_> = sections.iter().map(|(name, ..)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "",
+                ".text",
+                ".rodata",
+                ".rela.text",
+                ".symtab",
+                ".strtab",
+                ".shstrtab",
+                ".note.GNU-stack"
+            ]
+        );
+        let data = |index: usize| {
+            let (_, _, offset, size) = sections[index];
+            &object[offset..offset + size]
+        };
+        assert_eq!(data(1), [0xe8, 0, 0, 0, 0, 0x0f, 0x05]);
+        assert_eq!(data(2), b"hi\0");
+        assert_eq!(data(5), b"\0_start\0debug_s\0");
+
+        // one PLT32 relocation against the first undefined symbol
+        let rela = data(3);
+        assert_eq!(rela.len(), 24);
+        assert_eq!(u64_at(rela, 0), 1);
+        assert_eq!(u64_at(rela, 8), 4 << 32 | R_X86_64_PLT32);
+        assert_eq!(u64_at(rela, 16) as i64, -4);
+    }
+}
@@ -0,0 +1,559 @@
+use std::collections::HashMap;
+
+use mellow_parse::Identifier;
+
+use crate::{assembly::Assembly, data::Data, register::Size};
+
+/// What a relocation points at.
+pub enum Target {
+    /// An external function, reached through the PLT.
+    Function(Identifier),
+    /// A string constant in `.rodata`, reached relative to `rip`.
+    String(usize),
+}
+
+/// A 32-bit field at `offset` that the linker has to fill in.
+pub struct Relocation {
+    pub offset: usize,
+    pub target: Target,
+}
+
+/// Machine code of a program.
+pub struct Code {
+    pub bytes: Vec<u8>,
+    pub relocations: Vec<Relocation>,
+}
+
+/// The part of an instruction that ModRM (and SIB) describe.
+enum Operand {
+    Register(u8),
+    Memory {
+        base: Option<u8>,
+        index: Option<(u8, u8)>,
+        displacement: i32,
+    },
+    String(usize),
+}
+
+impl From<&Data> for Operand {
+    fn from(data: &Data) -> Self {
+        match data {
+            Data::Register(register) => Self::Register(register.kind().number()),
+            Data::Stack(offset) => Self::Memory {
+                base: Some(5),
+                index: None,
+                displacement: -(*offset as i32),
+            },
+            Data::Address {
+                base,
+                index,
+                displacement,
+            } => Self::Memory {
+                base: base.as_ref().map(|base| base.kind().number()),
+                index: index
+                    .as_ref()
+                    .map(|(index, scale)| (index.kind().number(), *scale)),
+                displacement: *displacement as i32,
+            },
+            Data::String(id) => Self::String(*id),
+            Data::Integer(_) => unreachable!("immediates are encoded by their instruction"),
+        }
+    }
+}
+
+/// Whether an instruction on `data` works on all 64 bits.
+fn wide(data: &Data) -> bool {
+    match data {
+        Data::Register(register) => *register.size() == Size::Qword,
+        _ => true,
+    }
+}
+
+fn small(value: i128) -> bool {
+    i8::try_from(value).is_ok()
+}
+
+#[derive(Default)]
+struct Encoder {
+    bytes: Vec<u8>,
+    labels: HashMap<u64, usize>,
+    /// Offsets of `rel32` fields and the labels they jump to.
+    jumps: Vec<(usize, u64)>,
+    relocations: Vec<Relocation>,
+}
+
+impl Encoder {
+    fn push(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Emits the REX prefix, `opcode` and the ModRM bytes for `reg` and `rm`.
+    /// Byte registers 4 to 7 mean `spl` to `dil` only with a REX prefix.
+    fn instruction(&mut self, wide: bool, opcode: &[u8], reg: u8, rm: &Operand, byte: bool) {
+        let (index, base) = match rm {
+            Operand::Register(number) => (0, *number),
+            Operand::Memory { base, index, .. } => {
+                (index.map_or(0, |(index, _)| index), base.unwrap_or(0))
+            }
+            Operand::String(_) => (0, 0),
+        };
+        let rex = 0x40 | (wide as u8) << 3 | (reg >> 3) << 2 | (index >> 3) << 1 | base >> 3;
+        let low_byte = byte && matches!(rm, Operand::Register(4..=7));
+        if rex != 0x40 || low_byte {
+            self.push(&[rex]);
+        }
+        self.push(opcode);
+
+        let reg = (reg & 7) << 3;
+        match *rm {
+            Operand::Register(number) => self.push(&[0xc0 | reg | number & 7]),
+            Operand::Memory {
+                base,
+                index,
+                displacement,
+            } => self.memory(reg, base, index, displacement),
+            Operand::String(id) => {
+                // rip-relative
+                self.push(&[reg | 5]);
+                self.relocations.push(Relocation {
+                    offset: self.bytes.len(),
+                    target: Target::String(id),
+                });
+                self.push(&[0; 4]);
+            }
+        }
+    }
+
+    fn memory(&mut self, reg: u8, base: Option<u8>, index: Option<(u8, u8)>, displacement: i32) {
+        let Some(base) = base else {
+            // a SIB base of rbp under mod 00 stands for no base and a disp32
+            let (index, scale) = index.unwrap();
+            self.push(&[reg | 4, scale_bits(scale) << 6 | (index & 7) << 3 | 5]);
+            self.push(&displacement.to_le_bytes());
+            return;
+        };
+        // rbp and r13 always need a displacement, as mod 00 means something else
+        let mode = match displacement {
+            0 if base & 7 != 5 => 0x00,
+            _ if i8::try_from(displacement).is_ok() => 0x40,
+            _ => 0x80,
+        };
+        // rsp and r12 as the base need a SIB byte, as 100 in rm means "SIB"
+        match index {
+            Some((index, scale)) => {
+                self.push(&[
+                    mode | reg | 4,
+                    scale_bits(scale) << 6 | (index & 7) << 3 | base & 7,
+                ]);
+            }
+            None if base & 7 == 4 => self.push(&[mode | reg | 4, 0x24]),
+            None => self.push(&[mode | reg | base & 7]),
+        }
+        match mode {
+            0x40 => self.push(&[displacement as u8]),
+            0x80 => self.push(&displacement.to_le_bytes()),
+            _ => {}
+        }
+    }
+
+    /// `add`, `sub`, `cmp` or `xor` with the `/r` opcode and the extension
+    /// used with immediates.
+    fn arithmetic(&mut self, opcode: u8, extension: u8, to: &Data, from: &Data) {
+        match from {
+            Data::Integer(value) if small(*value) => {
+                self.instruction(wide(to), &[0x83], extension, &to.into(), false);
+                self.push(&[*value as u8]);
+            }
+            Data::Integer(value) => {
+                self.instruction(wide(to), &[0x81], extension, &to.into(), false);
+                self.push(&(*value as i32).to_le_bytes());
+            }
+            Data::Register(register) => {
+                let number = register.kind().number();
+                self.instruction(wide(to), &[opcode], number, &to.into(), false);
+            }
+            _ => unreachable!("both operands of {opcode:#x} are in memory"),
+        }
+    }
+
+    fn mov(&mut self, to: &Data, from: &Data) {
+        match (to, from) {
+            (Data::Register(register), Data::Integer(value)) if i32::try_from(*value).is_err() => {
+                let number = register.kind().number();
+                self.push(&[0x48 | number >> 3, 0xb8 + (number & 7)]);
+                self.push(&(*value as i64).to_le_bytes());
+            }
+            (_, Data::Integer(value)) => {
+                self.instruction(true, &[0xc7], 0, &to.into(), false);
+                self.push(&(*value as i32).to_le_bytes());
+            }
+            (Data::Register(register), Data::String(_)) => {
+                // the address is taken with `lea` rather than as an absolute
+                let number = register.kind().number();
+                self.instruction(true, &[0x8d], number, &from.into(), false);
+            }
+            (_, Data::Register(register)) => {
+                let number = register.kind().number();
+                self.instruction(wide(from), &[0x89], number, &to.into(), false);
+            }
+            (Data::Register(register), _) => {
+                let number = register.kind().number();
+                self.instruction(wide(to), &[0x8b], number, &from.into(), false);
+            }
+            _ => unreachable!("both operands of mov are in memory"),
+        }
+    }
+
+    fn jump(&mut self, opcode: &[u8], label: u64) {
+        self.push(opcode);
+        self.jumps.push((self.bytes.len(), label));
+        self.push(&[0; 4]);
+    }
+
+    fn shift(&mut self, extension: u8, to: &Data, count: &Data) {
+        let Data::Integer(count) = count else {
+            unreachable!("shift counts are immediates");
+        };
+        self.instruction(wide(to), &[0xc1], extension, &to.into(), false);
+        self.push(&[*count as u8]);
+    }
+
+    fn set(&mut self, opcode: u8, to: &Data) {
+        self.instruction(false, &[0x0f, opcode], 0, &to.into(), true);
+    }
+
+    fn stack(&mut self, opcode: u8, data: &Data) {
+        match data {
+            Data::Register(register) => {
+                let number = register.kind().number();
+                if number >= 8 {
+                    self.push(&[0x41]);
+                }
+                self.push(&[opcode + (number & 7)]);
+            }
+            Data::Integer(value) if small(*value) => self.push(&[0x6a, *value as u8]),
+            Data::Integer(value) => {
+                self.push(&[0x68]);
+                self.push(&(*value as i32).to_le_bytes());
+            }
+            _ => unreachable!("only registers and immediates go through the stack"),
+        }
+    }
+
+    fn encode(&mut self, instruction: &Assembly) {
+        match instruction {
+            Assembly::Label(id) => {
+                self.labels.insert(*id, self.bytes.len());
+            }
+            Assembly::Mov(to, from) => self.mov(to, from),
+            Assembly::Cmp(to, from) => self.arithmetic(0x39, 7, to, from),
+            Assembly::Add(to, from) => self.arithmetic(0x01, 0, to, from),
+            Assembly::Sub(to, from) => self.arithmetic(0x29, 5, to, from),
+            Assembly::Xor(to, from) => self.arithmetic(0x31, 6, to, from),
+            Assembly::Test(to, Data::Register(register)) => {
+                let number = register.kind().number();
+                self.instruction(wide(to), &[0x85], number, &to.into(), false);
+            }
+            Assembly::Imul(Data::Register(register), Data::Integer(value)) => {
+                let number = register.kind().number();
+                let operand = Operand::Register(number);
+                if small(*value) {
+                    self.instruction(true, &[0x6b], number, &operand, false);
+                    self.push(&[*value as u8]);
+                } else {
+                    self.instruction(true, &[0x69], number, &operand, false);
+                    self.push(&(*value as i32).to_le_bytes());
+                }
+            }
+            Assembly::Imul(Data::Register(register), from) => {
+                let number = register.kind().number();
+                self.instruction(true, &[0x0f, 0xaf], number, &from.into(), false);
+            }
+            Assembly::Idiv(data) => self.instruction(true, &[0xf7], 7, &data.into(), false),
+            Assembly::Cqo => self.push(&[0x48, 0x99]),
+            Assembly::Lea(Data::Register(register), address) => {
+                let number = register.kind().number();
+                self.instruction(true, &[0x8d], number, &address.into(), false);
+            }
+            Assembly::Shl(to, count) => self.shift(4, to, count),
+            Assembly::Shr(to, count) => self.shift(5, to, count),
+            Assembly::Sar(to, count) => self.shift(7, to, count),
+            Assembly::Sete(to) => self.set(0x94, to),
+            Assembly::Setg(to) => self.set(0x9f, to),
+            Assembly::Setl(to) => self.set(0x9c, to),
+            Assembly::Jmp(label) => self.jump(&[0xe9], *label),
+            Assembly::Je(label) => self.jump(&[0x0f, 0x84], *label),
+            Assembly::Jne(label) => self.jump(&[0x0f, 0x85], *label),
+            Assembly::Jg(label) => self.jump(&[0x0f, 0x8f], *label),
+            Assembly::Jl(label) => self.jump(&[0x0f, 0x8c], *label),
+            Assembly::Jge(label) => self.jump(&[0x0f, 0x8d], *label),
+            Assembly::Jle(label) => self.jump(&[0x0f, 0x8e], *label),
+            Assembly::Call(label) => {
+                self.push(&[0xe8]);
+                self.relocations.push(Relocation {
+                    offset: self.bytes.len(),
+                    target: Target::Function(*label),
+                });
+                self.push(&[0; 4]);
+            }
+            Assembly::Push(data) => self.stack(0x50, data),
+            Assembly::Pop(data) => self.stack(0x58, data),
+            Assembly::Syscall => self.push(&[0x0f, 0x05]),
+            Assembly::String(..) | Assembly::Empty => {}
+            _ => unreachable!("{instruction} has no encoding"),
+        }
+    }
+}
+
+fn scale_bits(scale: u8) -> u8 {
+    match scale {
+        1 => 0,
+        2 => 1,
+        4 => 2,
+        8 => 3,
+        _ => unreachable!("scale must be 1, 2, 4 or 8"),
+    }
+}
+
+/// Encodes `text` to x86-64 machine code. Every jump takes a 32-bit
+/// displacement, so labels are resolved in a single pass at the end.
+pub fn encode(text: &[Assembly]) -> Code {
+    let mut encoder = Encoder::default();
+    for instruction in text {
+        encoder.encode(instruction);
+    }
+    for (offset, label) in &encoder.jumps {
+        let relative = encoder.labels[label] as i32 - (*offset as i32 + 4);
+        encoder.bytes[*offset..*offset + 4].copy_from_slice(&relative.to_le_bytes());
+    }
+    Code {
+        bytes: encoder.bytes,
+        relocations: encoder.relocations,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::register::{Register, RegisterKind};
+
+    fn register(kind: RegisterKind, size: Size) -> Data {
+        Data::Register(Register::new(kind, size))
+    }
+
+    fn qword(kind: RegisterKind) -> Data {
+        register(kind, Size::Qword)
+    }
+
+    fn address(
+        base: Option<RegisterKind>,
+        index: Option<(RegisterKind, u8)>,
+        displacement: i128,
+    ) -> Data {
+        Data::Address {
+            base: base.map(|kind| Register::new(kind, Size::Qword)),
+            index: index.map(|(kind, scale)| (Register::new(kind, Size::Qword), scale)),
+            displacement,
+        }
+    }
+
+    fn bytes(instruction: Assembly) -> Vec<u8> {
+        encode(&[instruction]).bytes
+    }
+
+    // expected bytes are what GNU as assembles the same instructions to
+
+    #[test]
+    fn stack_slots() {
+        use RegisterKind::*;
+        assert_eq!(
+            bytes(Assembly::Mov(qword(A), Data::Stack(8))),
+            [0x48, 0x8b, 0x45, 0xf8]
+        );
+        assert_eq!(
+            bytes(Assembly::Mov(Data::Stack(8), qword(B))),
+            [0x48, 0x89, 0x5d, 0xf8]
+        );
+        assert_eq!(
+            bytes(Assembly::Mov(Data::Stack(8), Data::Integer(5))),
+            [0x48, 0xc7, 0x45, 0xf8, 0x05, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn bases_that_need_a_displacement() {
+        use RegisterKind::*;
+        let lea = |base| bytes(Assembly::Lea(qword(A), address(Some(base), None, 0)));
+        assert_eq!(lea(Bp), [0x48, 0x8d, 0x45, 0x00]);
+        assert_eq!(lea(R13), [0x49, 0x8d, 0x45, 0x00]);
+    }
+
+    #[test]
+    fn bases_that_need_a_sib() {
+        use RegisterKind::*;
+        assert_eq!(
+            bytes(Assembly::Lea(qword(A), address(Some(Sp), None, 0))),
+            [0x48, 0x8d, 0x04, 0x24]
+        );
+        assert_eq!(
+            bytes(Assembly::Lea(qword(A), address(Some(R12), None, 8))),
+            [0x49, 0x8d, 0x44, 0x24, 0x08]
+        );
+    }
+
+    #[test]
+    fn scaled_indices() {
+        use RegisterKind::*;
+        assert_eq!(
+            bytes(Assembly::Lea(qword(A), address(Some(B), Some((C, 4)), 0))),
+            [0x48, 0x8d, 0x04, 0x8b]
+        );
+        assert_eq!(
+            bytes(Assembly::Lea(qword(A), address(None, Some((C, 8)), 16))),
+            [0x48, 0x8d, 0x04, 0xcd, 0x10, 0, 0, 0]
+        );
+        assert_eq!(
+            bytes(Assembly::Lea(
+                qword(A),
+                address(Some(Bp), Some((R13, 2)), 200)
+            )),
+            [0x4a, 0x8d, 0x84, 0x6d, 0xc8, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn byte_registers() {
+        use RegisterKind::*;
+        assert_eq!(
+            bytes(Assembly::Sete(register(Si, Size::Byte))),
+            [0x40, 0x0f, 0x94, 0xc6]
+        );
+        assert_eq!(
+            bytes(Assembly::Sete(register(A, Size::Byte))),
+            [0x0f, 0x94, 0xc0]
+        );
+        assert_eq!(
+            bytes(Assembly::Sete(register(R8, Size::Byte))),
+            [0x41, 0x0f, 0x94, 0xc0]
+        );
+    }
+
+    #[test]
+    fn immediates() {
+        use RegisterKind::*;
+        let mov = |kind, value| bytes(Assembly::Mov(qword(kind), Data::Integer(value)));
+        assert_eq!(mov(A, 1), [0x48, 0xc7, 0xc0, 0x01, 0, 0, 0]);
+        assert_eq!(mov(R9, -1), [0x49, 0xc7, 0xc1, 0xff, 0xff, 0xff, 0xff]);
+        assert_eq!(mov(A, 1 << 32), [0x48, 0xb8, 0, 0, 0, 0, 0x01, 0, 0, 0]);
+        assert_eq!(
+            mov(R10, i64::MIN as i128),
+            [0x49, 0xba, 0, 0, 0, 0, 0, 0, 0, 0x80]
+        );
+        assert_eq!(bytes(Assembly::Push(Data::Integer(5))), [0x6a, 0x05]);
+        assert_eq!(
+            bytes(Assembly::Push(Data::Integer(1000))),
+            [0x68, 0xe8, 0x03, 0, 0]
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        use RegisterKind::*;
+        assert_eq!(
+            bytes(Assembly::Add(qword(A), Data::Integer(1))),
+            [0x48, 0x83, 0xc0, 0x01]
+        );
+        // as picks the shorter form that only takes rax, this is the general one
+        assert_eq!(
+            bytes(Assembly::Add(qword(A), Data::Integer(1000))),
+            [0x48, 0x81, 0xc0, 0xe8, 0x03, 0, 0]
+        );
+        assert_eq!(
+            bytes(Assembly::Add(qword(R12), qword(B))),
+            [0x49, 0x01, 0xdc]
+        );
+        assert_eq!(
+            bytes(Assembly::Sub(qword(Sp), Data::Integer(16))),
+            [0x48, 0x83, 0xec, 0x10]
+        );
+        assert_eq!(
+            bytes(Assembly::Imul(qword(A), Data::Integer(3))),
+            [0x48, 0x6b, 0xc0, 0x03]
+        );
+        assert_eq!(
+            bytes(Assembly::Imul(qword(A), Data::Integer(1000))),
+            [0x48, 0x69, 0xc0, 0xe8, 0x03, 0, 0]
+        );
+        assert_eq!(
+            bytes(Assembly::Imul(qword(R9), qword(B))),
+            [0x4c, 0x0f, 0xaf, 0xcb]
+        );
+        assert_eq!(bytes(Assembly::Idiv(qword(C))), [0x48, 0xf7, 0xf9]);
+        assert_eq!(bytes(Assembly::Cqo), [0x48, 0x99]);
+        assert_eq!(
+            bytes(Assembly::Test(qword(B), qword(B))),
+            [0x48, 0x85, 0xdb]
+        );
+        let edi = register(Di, Size::Dword);
+        assert_eq!(bytes(Assembly::Xor(edi.clone(), edi)), [0x31, 0xff]);
+        let r8d = register(R8, Size::Dword);
+        assert_eq!(bytes(Assembly::Xor(r8d.clone(), r8d)), [0x45, 0x31, 0xc0]);
+    }
+
+    #[test]
+    fn shifts() {
+        use RegisterKind::*;
+        assert_eq!(
+            bytes(Assembly::Shl(qword(A), Data::Integer(3))),
+            [0x48, 0xc1, 0xe0, 0x03]
+        );
+        assert_eq!(
+            bytes(Assembly::Sar(qword(D), Data::Integer(63))),
+            [0x48, 0xc1, 0xfa, 0x3f]
+        );
+        assert_eq!(
+            bytes(Assembly::Shr(qword(D), Data::Integer(61))),
+            [0x48, 0xc1, 0xea, 0x3d]
+        );
+    }
+
+    #[test]
+    fn stack() {
+        use RegisterKind::*;
+        assert_eq!(bytes(Assembly::Push(qword(B))), [0x53]);
+        assert_eq!(bytes(Assembly::Push(qword(R12))), [0x41, 0x54]);
+        assert_eq!(bytes(Assembly::Pop(qword(R15))), [0x41, 0x5f]);
+        assert_eq!(bytes(Assembly::Syscall), [0x0f, 0x05]);
+    }
+
+    #[test]
+    fn jumps() {
+        let forward = encode(&[Assembly::Je(1), Assembly::Cqo, Assembly::Label(1)]);
+        assert_eq!(forward.bytes, [0x0f, 0x84, 0x02, 0, 0, 0, 0x48, 0x99]);
+        let backward = encode(&[Assembly::Label(1), Assembly::Jmp(1)]);
+        assert_eq!(backward.bytes, [0xe9, 0xfb, 0xff, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn relocations() {
+        use RegisterKind::*;
+        let code = encode(&[
+            Assembly::Call(Identifier::from("debug_s")),
+            Assembly::Mov(qword(B), Data::String(3)),
+        ]);
+        assert_eq!(code.bytes, [0xe8, 0, 0, 0, 0, 0x48, 0x8d, 0x1d, 0, 0, 0, 0]);
+        assert!(matches!(
+            code.relocations.as_slice(),
+            [
+                Relocation {
+                    offset: 1,
+                    target: Target::Function(_)
+                },
+                Relocation {
+                    offset: 8,
+                    target: Target::String(3)
+                },
+            ]
+        ));
+    }
+}
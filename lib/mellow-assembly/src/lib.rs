@@ -1,37 +1,61 @@
 mod assembly;
 mod data;
+mod elf;
+mod encode;
 mod lifetime;
+mod link;
 mod optimize;
 mod register;
+mod scan;
+mod select;
+mod syntax;
 
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
 
 use assembly::Assembly;
 use data::Data;
-use ir::tac::Instruction;
+use ir::{cfg::Graph, tac::Instruction};
+pub use link::LinkError;
+use mellow_parse::Identifier;
 pub use optimize::optimize;
 use register::{Register, RegisterKind, Size};
+use select::Selection;
+use syntax::Spelled;
+pub use syntax::Syntax;
 
-use crate::lifetime::allocate;
+use crate::lifetime::Allocation;
 
 macro_rules! arithmetic {
-    ($operation:ident, $to:expr, $left:expr, $right:expr, $allocated:expr, $output:expr) => {
+    ($operation:ident, $to:expr, $left:expr, $right:expr, $allocated:expr, $selection:expr, $output:expr) => {
         let to = register($to, Size::Qword, $allocated);
         let left = register($left, Size::Qword, $allocated);
-        let right = register($right, Size::Qword, $allocated);
-        $output.extend(vec![
-            Assembly::$operation(left.clone(), right),
-            Assembly::Mov(to, left),
-        ]);
+        let right = operand($right, $allocated, $selection);
+        if to != right {
+            $output.extend(vec![
+                Assembly::Mov(to.clone(), left),
+                Assembly::$operation(to, right),
+            ]);
+        } else {
+            // `to` shares a register with `right`, so compute in rax
+            let rax = Data::Register(Register::new(RegisterKind::A, Size::Qword));
+            $output.extend(vec![
+                Assembly::Mov(rax.clone(), left),
+                Assembly::$operation(rax.clone(), right),
+                Assembly::Mov(to, rax),
+            ]);
+        }
     };
 }
 
 macro_rules! comparision {
-    ($operation:ident, $to:expr, $left:expr, $right:expr, $allocated:expr, $output:expr) => {
+    ($operation:ident, $to:expr, $left:expr, $right:expr, $allocated:expr, $selection:expr, $output:expr) => {
         let byte_to = register($to, Size::Byte, $allocated);
         let qword_to = register($to, Size::Qword, $allocated);
         let left = register($left, Size::Qword, $allocated);
-        let right = register($right, Size::Qword, $allocated);
+        let right = operand($right, $allocated, $selection);
         $output.extend(vec![
             Assembly::Cmp(left, right),
             Assembly::Mov(qword_to, Data::Integer(0)),
@@ -46,10 +70,187 @@ fn register(id: u64, size: Size, allocated: &HashMap<u64, RegisterKind>) -> Data
     Data::Register(register)
 }
 
+/// `id` as an immediate if selection folded it, or its register.
+fn operand(id: u64, allocated: &HashMap<u64, RegisterKind>, selection: &Selection) -> Data {
+    match selection.immediates.get(&id) {
+        Some(value) => Data::Integer(*value),
+        None => register(id, Size::Qword, allocated),
+    }
+}
+
+fn qword(id: u64, allocated: &HashMap<u64, RegisterKind>) -> Register {
+    Register::new(allocated[&id].clone(), Size::Qword)
+}
+
+/// Adds through `lea` when that folds a scaled index or saves a `mov`.
+fn add(
+    to: u64,
+    left: u64,
+    right: u64,
+    allocated: &HashMap<u64, RegisterKind>,
+    selection: &Selection,
+    output: &mut Vec<Assembly>,
+) {
+    let (other, index) = match selection.scaled.get(&left) {
+        Some(index) => (right, Some(*index)),
+        None => (left, selection.scaled.get(&right).copied()),
+    };
+    let (base, displacement) = match (index, selection.immediates.get(&other)) {
+        (Some(_), Some(value)) => (None, *value),
+        (Some(_), None) => (Some(other), 0),
+        (None, _) => match selection.immediates.get(&right) {
+            Some(value) if allocated.get(&to) != allocated.get(&left) => (Some(left), *value),
+            _ => {
+                arithmetic!(Add, to, left, right, allocated, selection, output);
+                return;
+            }
+        },
+    };
+    let address = Data::Address {
+        base: base.map(|id| qword(id, allocated)),
+        index: index.map(|(id, scale)| (qword(id, allocated), scale)),
+        displacement,
+    };
+    output.push(Assembly::Lea(register(to, Size::Qword, allocated), address));
+}
+
+/// Multiplies by a constant with a shift or `lea` where possible.
+fn multiply(
+    to: u64,
+    left: u64,
+    value: i128,
+    allocated: &HashMap<u64, RegisterKind>,
+    output: &mut Vec<Assembly>,
+) {
+    let to = register(to, Size::Qword, allocated);
+    if let Some(shift) = select::power_of_two(value) {
+        let left = register(left, Size::Qword, allocated);
+        output.push(Assembly::Mov(to.clone(), left));
+        if shift > 0 {
+            output.push(Assembly::Shl(to, Data::Integer(shift as i128)));
+        }
+    } else if matches!(value, 3 | 5 | 9) {
+        let left = qword(left, allocated);
+        let address = Data::Address {
+            base: Some(left.clone()),
+            index: Some((left, value as u8 - 1)),
+            displacement: 0,
+        };
+        output.push(Assembly::Lea(to, address));
+    } else {
+        output.extend(vec![
+            Assembly::Mov(to.clone(), register(left, Size::Qword, allocated)),
+            Assembly::Imul(to, Data::Integer(value)),
+        ]);
+    }
+}
+
+/// Signed division by `2 ** shift`, which rounds towards zero like `idiv`:
+/// negative dividends are biased by `2 ** shift - 1` before the shift.
+fn divide(
+    to: u64,
+    left: u64,
+    shift: u32,
+    allocated: &HashMap<u64, RegisterKind>,
+    output: &mut Vec<Assembly>,
+) {
+    let to = register(to, Size::Qword, allocated);
+    let left = register(left, Size::Qword, allocated);
+    if shift == 0 {
+        output.push(Assembly::Mov(to, left));
+        return;
+    }
+    let rax = Data::Register(Register::new(RegisterKind::A, Size::Qword));
+    output.extend(vec![
+        Assembly::Mov(rax.clone(), left.clone()),
+        Assembly::Sar(rax.clone(), Data::Integer(63)),
+        Assembly::Shr(rax.clone(), Data::Integer(64 - shift as i128)),
+        Assembly::Add(rax.clone(), left),
+        Assembly::Sar(rax.clone(), Data::Integer(shift as i128)),
+        Assembly::Mov(to, rax),
+    ]);
+}
+
+/// Caller-saved registers holding values that are still needed after a call,
+/// other than its result.
+fn saved(
+    live: &HashSet<u64>,
+    result: Option<u64>,
+    allocated: &HashMap<u64, RegisterKind>,
+) -> Vec<Data> {
+    let used: HashSet<_> = live
+        .iter()
+        .filter(|id| Some(**id) != result)
+        .filter_map(|id| allocated.get(id))
+        .collect();
+    RegisterKind::allocable()
+        .into_iter()
+        .filter(|register| register.is_caller_saved() && used.contains(register))
+        .map(|register| Data::Register(Register::new(register, Size::Qword)))
+        .collect()
+}
+
+/// Calls `label` the System V way: `saved` registers are pushed around the
+/// call and the stack is 16-byte aligned at it.
+fn call(label: Identifier, arguments: Vec<Assembly>, saved: Vec<Data>, output: &mut Vec<Assembly>) {
+    let padding = saved.len() % 2 == 1;
+    let rsp = Data::Register(Register::new(RegisterKind::Sp, Size::Qword));
+    output.extend(saved.iter().cloned().map(Assembly::Push));
+    if padding {
+        output.push(Assembly::Sub(rsp.clone(), Data::Integer(8)));
+    }
+    output.extend(arguments);
+    output.push(Assembly::Call(label));
+    if padding {
+        output.push(Assembly::Add(rsp, Data::Integer(8)));
+    }
+    output.extend(saved.into_iter().rev().map(Assembly::Pop));
+}
+
+/// String constants, each stored once.
+#[derive(Default)]
+struct Strings {
+    ids: HashMap<String, usize>,
+    output: Vec<Assembly>,
+}
+
+impl Strings {
+    fn intern(&mut self, value: String) -> usize {
+        if let Some(id) = self.ids.get(&value) {
+            return *id;
+        }
+        let id = self.output.len();
+        self.ids.insert(value.clone(), id);
+        self.output.push(Assembly::String(id, value));
+        id
+    }
+}
+
+/// Gives every variable of `graph` a stack slot below the first `taken` ones.
+fn variables(graph: &Graph, taken: usize) -> HashMap<Identifier, u32> {
+    let mut variables = HashMap::new();
+    for block in &graph.blocks {
+        for instruction in &block.body {
+            if let Instruction::Get { identifier, .. } | Instruction::Set { identifier, .. } =
+                instruction
+                && !variables.contains_key(identifier)
+            {
+                let offset = (taken + variables.len() + 1) as u32 * 8;
+                variables.insert(*identifier, offset);
+            }
+        }
+    }
+    variables
+}
+
 fn generate(
     instruction: Instruction,
     output: &mut Vec<Assembly>,
     allocated: &HashMap<u64, RegisterKind>,
+    live: &HashSet<u64>,
+    variables: &HashMap<Identifier, u32>,
+    strings: &mut Strings,
+    selection: &Selection,
 ) {
     match instruction {
         Instruction::Label(id) => {
@@ -60,14 +261,38 @@ fn generate(
             let value = Data::Integer(value);
             output.push(Assembly::Mov(to, value));
         }
-        Instruction::Add { to, left, right } => {
-            arithmetic!(Add, to, left, right, allocated, output);
+        Instruction::String { to, value } => {
+            let to = register(to, Size::Qword, allocated);
+            let id = strings.intern(value);
+            output.push(Assembly::Mov(to, Data::String(id)));
         }
-        Instruction::Subtract { to, left, right } => {
-            arithmetic!(Sub, to, left, right, allocated, output);
+        Instruction::Add { to, left, right } => {
+            add(to, left, right, allocated, selection, output);
         }
-        Instruction::Multiply { to, left, right } => {
-            arithmetic!(Imul, to, left, right, allocated, output);
+        Instruction::Subtract { to, left, right } => match selection.immediates.get(&right) {
+            Some(value) if allocated.get(&to) != allocated.get(&left) => {
+                let address = Data::Address {
+                    base: Some(qword(left, allocated)),
+                    index: None,
+                    displacement: -value,
+                };
+                output.push(Assembly::Lea(register(to, Size::Qword, allocated), address));
+            }
+            _ => {
+                arithmetic!(Sub, to, left, right, allocated, selection, output);
+            }
+        },
+        Instruction::Multiply { to, left, right } => match selection.immediates.get(&right) {
+            Some(value) => multiply(to, left, *value, allocated, output),
+            None => {
+                arithmetic!(Imul, to, left, right, allocated, selection, output);
+            }
+        },
+        Instruction::Divide { to, left, right }
+            if let Some(value) = selection.immediates.get(&right) =>
+        {
+            let shift = select::power_of_two(*value).unwrap();
+            divide(to, left, shift, allocated, output);
         }
         Instruction::Divide { to, left, right } => {
             let to = register(to, Size::Qword, allocated);
@@ -81,23 +306,54 @@ fn generate(
                 Assembly::Mov(to, rax),
             ]);
         }
+        Instruction::Remainder { to, left, right } => {
+            let to = register(to, Size::Qword, allocated);
+            let left = register(left, Size::Qword, allocated);
+            let right = register(right, Size::Qword, allocated);
+            let rax = Data::Register(Register::new(RegisterKind::A, Size::Qword));
+            let rdx = Data::Register(Register::new(RegisterKind::D, Size::Qword));
+            output.extend(vec![
+                Assembly::Mov(rax, left),
+                Assembly::Cqo,
+                Assembly::Idiv(right),
+                Assembly::Mov(to, rdx),
+            ]);
+        }
+        Instruction::Power { to, left, right } => {
+            let left = operand(left, allocated, selection);
+            let right = operand(right, allocated, selection);
+            let rdi = Data::Register(Register::new(RegisterKind::Di, Size::Qword));
+            let rsi = Data::Register(Register::new(RegisterKind::Si, Size::Qword));
+            let rax = Data::Register(Register::new(RegisterKind::A, Size::Qword));
+            // goes through the stack, so it works whatever registers hold the operands
+            let arguments = vec![
+                Assembly::Push(left),
+                Assembly::Push(right),
+                Assembly::Pop(rsi),
+                Assembly::Pop(rdi),
+            ];
+            let saved = saved(live, Some(to), allocated);
+            call(Identifier::from("power_i64"), arguments, saved, output);
+            let to = register(to, Size::Qword, allocated);
+            output.push(Assembly::Mov(to, rax));
+        }
         Instruction::Equal { to, left, right } => {
-            comparision!(Sete, to, left, right, allocated, output);
+            comparision!(Sete, to, left, right, allocated, selection, output);
         }
         Instruction::Greater { to, left, right } => {
-            comparision!(Setg, to, left, right, allocated, output);
+            comparision!(Setg, to, left, right, allocated, selection, output);
         }
         Instruction::Less { to, left, right } => {
-            comparision!(Setl, to, left, right, allocated, output);
+            comparision!(Setl, to, left, right, allocated, selection, output);
         }
         Instruction::Set { identifier, from } => {
-            let to = Data::Identifier(identifier);
-            let from = register(from, Size::Qword, allocated);
+            let to = Data::Stack(variables[&identifier]);
+            let from = operand(from, allocated, selection);
             output.push(Assembly::Mov(to, from));
         }
         Instruction::Get { to, identifier } => {
             let to = register(to, Size::Qword, allocated);
-            let from = Data::Identifier(identifier);
+            let from = Data::Stack(variables[&identifier]);
             output.push(Assembly::Mov(to, from));
         }
         Instruction::Jump(to) => {
@@ -111,19 +367,228 @@ fn generate(
             ]);
         }
         Instruction::Call { label, value } => {
-            let value = register(value, Size::Qword, allocated);
+            let value = operand(value, allocated, selection);
             let rdi = Data::Register(Register::new(RegisterKind::Di, Size::Qword));
-            output.extend(vec![Assembly::Mov(rdi, value), Assembly::Call(label)]);
+            let saved = saved(live, None, allocated);
+            call(label, vec![Assembly::Mov(rdi, value)], saved, output);
+        }
+        Instruction::Phi { .. } => unreachable!("phis are removed by ssa::destruct"),
+    }
+}
+
+/// A comparison fused with the branch on its result.
+fn compare(
+    comparison: &Instruction,
+    jump: Assembly,
+    allocated: &HashMap<u64, RegisterKind>,
+    selection: &Selection,
+    output: &mut Vec<Assembly>,
+) {
+    let [left, right] = comparison.operands()[..] else {
+        unreachable!("comparisons have two operands");
+    };
+    let left = register(left, Size::Qword, allocated);
+    let right = operand(right, allocated, selection);
+    output.extend(vec![Assembly::Cmp(left, right), jump]);
+}
+
+/// Points spilled temporaries of `instruction` at scratch registers, loading
+/// the ones it reads. Returns the stores to do after it.
+fn reload(
+    instruction: &Instruction,
+    slots: &HashMap<u64, u32>,
+    registers: &mut HashMap<u64, RegisterKind>,
+    output: &mut Vec<Assembly>,
+) -> Vec<Assembly> {
+    let mut scratch = RegisterKind::scratch().into_iter();
+    let mut stores = Vec::new();
+    for operand in instruction.operands() {
+        if let Some(offset) = slots.get(&operand)
+            && !registers.contains_key(&operand)
+        {
+            let register = scratch.next().unwrap();
+            registers.insert(operand, register.clone());
+            let register = Data::Register(Register::new(register, Size::Qword));
+            output.push(Assembly::Mov(register, Data::Stack(*offset)));
+        }
+    }
+    if let Some(to) = instruction.result()
+        && let Some(offset) = slots.get(&to)
+    {
+        // operands are read before the result is written, so it can share
+        let register = RegisterKind::scratch()[0].clone();
+        registers.insert(to, register.clone());
+        let register = Data::Register(Register::new(register, Size::Qword));
+        stores.push(Assembly::Mov(Data::Stack(*offset), register));
+    }
+    stores
+}
+
+/// Register allocation strategy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Allocator {
+    /// Graph colouring, slower but with fewer spills.
+    Colouring,
+    LinearScan,
+}
+
+/// Generated code: the program itself and the read-only data it uses.
+pub struct Program {
+    pub text: Vec<Assembly>,
+    pub rodata: Vec<Assembly>,
+}
+
+impl Program {
+    /// Functions called from outside the program, in order of first use.
+    fn externs(&self) -> Vec<Identifier> {
+        let mut externs = Vec::new();
+        for instruction in &self.text {
+            if let Assembly::Call(label) = instruction
+                && !externs.contains(label)
+            {
+                externs.push(*label);
+            }
         }
-        _ => todo!(),
+        externs
+    }
+
+    /// The program as an ELF64 relocatable object.
+    pub fn object(&self) -> Vec<u8> {
+        elf::write(encode::encode(&self.text), &self.rodata)
+    }
+
+    /// The program as assembler source in `syntax`.
+    pub fn listing(&self, syntax: Syntax) -> impl Display + '_ {
+        Spelled(self, syntax)
+    }
+
+    /// The program linked with the relocatable objects in `runtime` as a
+    /// static executable.
+    pub fn executable(&self, runtime: &[&[u8]]) -> Result<Vec<u8>, LinkError> {
+        let object = self.object();
+        let mut objects = vec![object.as_slice()];
+        objects.extend_from_slice(runtime);
+        link::link(&objects)
     }
 }
 
-pub fn convert(tac: Vec<Instruction>) -> Vec<Assembly> {
+impl Display for Program {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Spelled(self, Syntax::Nasm).fmt(f)
+    }
+}
+
+/// Assembler source of the program.
+impl Display for Spelled<'_, Program> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let Spelled(program, syntax) = *self;
+        let directive = match syntax {
+            Syntax::Nasm => "",
+            Syntax::Gas => ".",
+        };
+        if syntax == Syntax::Gas {
+            writeln!(f, ".intel_syntax noprefix")?;
+        }
+        writeln!(f, "{directive}section .text")?;
+        match syntax {
+            Syntax::Nasm => writeln!(f, "global _start")?,
+            Syntax::Gas => writeln!(f, ".globl _start")?,
+        }
+        writeln!(f, "_start:")?;
+        for identifier in program.externs() {
+            writeln!(f, "{directive}extern {identifier}")?;
+        }
+        for instruction in &program.text {
+            writeln!(f, "{}", Spelled(instruction, syntax))?;
+        }
+        if !program.rodata.is_empty() {
+            writeln!(f, "{directive}section .rodata")?;
+            for constant in &program.rodata {
+                writeln!(f, "{}", Spelled(constant, syntax))?;
+            }
+        }
+        if syntax == Syntax::Gas {
+            // marks the stack as not executable
+            writeln!(f, ".section .note.GNU-stack,\"\",@progbits")?;
+        }
+        Ok(())
+    }
+}
+
+pub fn convert(graph: Graph, allocator: Allocator) -> Program {
     let mut output = Vec::new();
-    let allocated = allocate(&tac);
-    for instruction in tac.into_iter() {
-        generate(instruction, &mut output, &allocated);
+    let Allocation {
+        mut registers,
+        mut slots,
+    } = match allocator {
+        Allocator::Colouring => lifetime::allocate(&graph),
+        Allocator::LinearScan => scan::allocate(&graph),
+    };
+
+    let variables = variables(&graph, slots.len());
+    let count = slots.len() + variables.len();
+    if count > 0 {
+        // `push rbp` leaves the stack 8 bytes off, so the frame makes up for it
+        let size = count as i128 * 8 + if count.is_multiple_of(2) { 8 } else { 0 };
+        let rbp = Data::Register(Register::new(RegisterKind::Bp, Size::Qword));
+        let rsp = Data::Register(Register::new(RegisterKind::Sp, Size::Qword));
+        output.extend(vec![
+            Assembly::Push(rbp.clone()),
+            Assembly::Mov(rbp, rsp.clone()),
+            Assembly::Sub(rsp, Data::Integer(size)),
+        ]);
+    }
+
+    let live_out = lifetime::liveness(&graph);
+    let mut tac = graph.linearize();
+    let mut live_after = lifetime::live_after_calls(&tac, &live_out);
+    let nothing = HashSet::new();
+    let mut strings = Strings::default();
+    let mut selection = select::select(&mut tac, &slots);
+    // folded integers are never materialised, wherever they were allocated
+    registers.retain(|id, _| !selection.immediates.contains_key(id));
+    slots.retain(|id, _| !selection.immediates.contains_key(id));
+    for (position, instruction) in tac.into_iter().enumerate() {
+        let live = live_after.remove(&position);
+        let live = live.as_ref().unwrap_or(&nothing);
+        if selection.replaced.contains(&position) {
+            continue;
+        }
+        let stores = reload(&instruction, &slots, &mut registers, &mut output);
+        let spilled: Vec<_> = instruction
+            .operands()
+            .into_iter()
+            .chain(instruction.result())
+            .filter(|id| slots.contains_key(id))
+            .collect();
+        match selection.jumps.remove(&position) {
+            Some(jump) => compare(&instruction, jump, &registers, &selection, &mut output),
+            None => generate(
+                instruction,
+                &mut output,
+                &registers,
+                live,
+                &variables,
+                &mut strings,
+                &selection,
+            ),
+        }
+        output.extend(stores);
+        for id in spilled {
+            registers.remove(&id);
+        }
+    }
+
+    // exit(0)
+    let rax = Data::Register(Register::new(RegisterKind::A, Size::Qword));
+    let rdi = Data::Register(Register::new(RegisterKind::Di, Size::Qword));
+    output.extend(vec![
+        Assembly::Mov(rax, Data::Integer(60)),
+        Assembly::Mov(rdi, Data::Integer(0)),
+        Assembly::Syscall,
+    ]);
+    Program {
+        text: output,
+        rodata: strings.output,
     }
-    output
 }
@@ -1,114 +1,260 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
-use ir::tac::Instruction;
+use ir::{cfg::Graph, tac::Instruction};
 
 use crate::register::RegisterKind;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-struct Lifetime {
-    pub start: usize,
-    pub end: usize,
+/// Temporaries read and written by a block, where `uses` only holds the ones
+/// read before they are written.
+struct Usage {
+    uses: HashSet<u64>,
+    definitions: HashSet<u64>,
 }
 
-impl Lifetime {
-    pub fn overlaps(&self, other: &Self) -> bool {
-        self.start <= other.start && other.end <= self.end
-    }
+fn usage(graph: &Graph) -> Vec<Usage> {
+    graph
+        .blocks
+        .iter()
+        .map(|block| {
+            let mut uses = HashSet::new();
+            let mut definitions = HashSet::new();
+            let operands = block
+                .body
+                .iter()
+                .map(|instruction| (instruction.operands(), instruction.result()))
+                .chain(std::iter::once((block.terminator.operands(), None)));
+            for (operands, result) in operands {
+                for operand in operands {
+                    if !definitions.contains(&operand) {
+                        uses.insert(operand);
+                    }
+                }
+                definitions.extend(result);
+            }
+            Usage { uses, definitions }
+        })
+        .collect()
 }
 
-macro_rules! begin {
-    ($start:expr, $id:expr, $index:expr) => {
-        $start.insert($id, $index);
-    };
+/// Temporaries live on exit from each block, computed backwards until
+/// nothing changes, so values that flow around loops stay live in them.
+pub fn liveness(graph: &Graph) -> Vec<HashSet<u64>> {
+    let usage = usage(graph);
+    let mut live_in: Vec<HashSet<u64>> = vec![HashSet::new(); graph.blocks.len()];
+    let mut live_out: Vec<HashSet<u64>> = vec![HashSet::new(); graph.blocks.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (id, block) in graph.blocks.iter().enumerate().rev() {
+            let out: HashSet<u64> = block
+                .successors
+                .iter()
+                .flat_map(|successor| live_in[*successor].iter().copied())
+                .collect();
+            let in_: HashSet<u64> = usage[id]
+                .uses
+                .iter()
+                .copied()
+                .chain(out.difference(&usage[id].definitions).copied())
+                .collect();
+            if in_ != live_in[id] || out != live_out[id] {
+                live_in[id] = in_;
+                live_out[id] = out;
+                changed = true;
+            }
+        }
+    }
+    live_out
 }
 
-macro_rules! end {
-    ($lifetimes:expr, $start:expr, $id:expr, $index:expr) => {
-        let lifetime = Lifetime {
-            start: *$start.get(&$id).unwrap(),
-            end: $index,
-        };
-        $lifetimes.insert(*$id, lifetime);
-    };
+/// Temporaries live right after every call in linearized TAC, keyed by the
+/// position of the call.
+pub fn live_after_calls(
+    tac: &[Instruction],
+    live_out: &[HashSet<u64>],
+) -> HashMap<usize, HashSet<u64>> {
+    let blocks: Vec<(usize, usize)> = tac
+        .iter()
+        .enumerate()
+        .filter_map(|(position, instruction)| match instruction {
+            Instruction::Label(id) => Some((*id as usize, position + 1)),
+            _ => None,
+        })
+        .collect();
+    let mut output = HashMap::new();
+    for (index, (id, start)) in blocks.iter().enumerate() {
+        let end = blocks
+            .get(index + 1)
+            .map_or(tac.len(), |(_, next)| next - 1);
+        let mut live = live_out.get(*id).cloned().unwrap_or_default();
+        for position in (*start..end).rev() {
+            let instruction = &tac[position];
+            if matches!(
+                instruction,
+                Instruction::Call { .. } | Instruction::Power { .. }
+            ) {
+                output.insert(position, live.clone());
+            }
+            if let Some(to) = instruction.result() {
+                live.remove(&to);
+            }
+            live.extend(instruction.operands());
+        }
+    }
+    output
 }
 
-fn scan(tac: &Vec<Instruction>) -> HashMap<u64, Lifetime> {
-    let mut start = HashMap::new();
-    let mut lifetimes = HashMap::new();
-    for (index, instruction) in tac.iter().enumerate() {
-        match instruction {
-            Instruction::Add { to, left, right }
-            | Instruction::Subtract { to, left, right }
-            | Instruction::Multiply { to, left, right }
-            | Instruction::Divide { to, left, right }
-            | Instruction::Greater { to, left, right }
-            | Instruction::Less { to, left, right }
-            | Instruction::Equal { to, left, right } => {
-                begin!(start, to, index);
-                end!(lifetimes, start, left, index);
-                end!(lifetimes, start, right, index);
-            }
-            Instruction::JumpIf { condition, .. } => {
-                end!(lifetimes, start, condition, index);
+/// A temporary interferes with everything that is live where it is written.
+fn interference_graph(graph: &Graph, live_out: &[HashSet<u64>]) -> HashMap<u64, HashSet<u64>> {
+    let mut output: HashMap<u64, HashSet<u64>> = HashMap::new();
+    for (id, block) in graph.blocks.iter().enumerate() {
+        let mut live = live_out[id].clone();
+        live.extend(block.terminator.operands());
+        for instruction in block.body.iter().rev() {
+            if let Some(to) = instruction.result() {
+                live.remove(&to);
+                output.entry(to).or_default();
+                for other in live.iter() {
+                    output.get_mut(&to).unwrap().insert(*other);
+                    output.entry(*other).or_default().insert(to);
+                }
             }
-            Instruction::Integer { to, .. }
-            | Instruction::Get { to, .. }
-            | Instruction::String { to, .. } => {
-                begin!(start, to, index);
+            live.extend(instruction.operands());
+        }
+    }
+    output
+}
+
+/// How many loops each block is nested in. A back-edge is an edge to a block
+/// that dominates its source, and its loop is everything that reaches the
+/// source without passing through the target.
+fn loop_depth(graph: &Graph) -> Vec<u32> {
+    let dominators = graph.dominators();
+    let mut depth = vec![0; graph.blocks.len()];
+    for (id, block) in graph.blocks.iter().enumerate() {
+        for header in &block.successors {
+            if !dominators.dominates(*header, id) {
+                continue;
             }
-            Instruction::Set { from, .. } => {
-                end!(lifetimes, start, from, index);
+            let mut body = HashSet::from([*header]);
+            let mut stack = vec![id];
+            while let Some(current) = stack.pop() {
+                if body.insert(current) {
+                    stack.extend(&graph.blocks[current].predecessors);
+                }
             }
-            Instruction::Call { value, .. } => {
-                end!(lifetimes, start, value, index);
+            for member in body {
+                depth[member] += 1;
             }
-            _ => {}
         }
     }
-    lifetimes
+    depth
 }
 
-fn interference_graph(lifetimes: HashMap<u64, Lifetime>) -> HashMap<u64, HashSet<u64>> {
-    let mut graph = HashMap::new();
-
-    for id in lifetimes.keys() {
-        graph.insert(*id, HashSet::new());
+/// Estimated cost of keeping each temporary in memory: every read and write
+/// counts, ten times more for each loop around it.
+fn spill_costs(graph: &Graph) -> HashMap<u64, u64> {
+    let depth = loop_depth(graph);
+    let mut output: HashMap<u64, u64> = HashMap::new();
+    for (id, block) in graph.blocks.iter().enumerate() {
+        let weight = 10u64.saturating_pow(depth[id]);
+        let operands = block
+            .body
+            .iter()
+            .flat_map(|instruction| {
+                instruction
+                    .operands()
+                    .into_iter()
+                    .chain(instruction.result())
+            })
+            .chain(block.terminator.operands());
+        for operand in operands {
+            let cost = output.entry(operand).or_default();
+            *cost = cost.saturating_add(weight);
+        }
     }
+    output
+}
 
-    for a in lifetimes.iter() {
-        for b in lifetimes.iter() {
-            if a.1.overlaps(b.1) {
-                graph.get_mut(a.0).unwrap().insert(*b.0);
-                graph.get_mut(b.0).unwrap().insert(*a.0);
+/// Optimistic graph colouring: nodes with fewer neighbours than registers
+/// are set aside first, and when none are left the cheapest node to spill
+/// goes next. Returns the colouring and the nodes that did not get a colour.
+fn colour(
+    graph: &HashMap<u64, HashSet<u64>>,
+    costs: &HashMap<u64, u64>,
+    registers: &[RegisterKind],
+) -> (HashMap<u64, RegisterKind>, Vec<u64>) {
+    let mut degree: BTreeMap<u64, usize> =
+        graph.iter().map(|(id, edges)| (*id, edges.len())).collect();
+    let mut stack = Vec::with_capacity(degree.len());
+    while !degree.is_empty() {
+        let simple = degree
+            .iter()
+            .find(|(_, degree)| **degree < registers.len())
+            .map(|(id, _)| *id);
+        let id = simple.unwrap_or_else(|| {
+            let ratio = |id: &u64| costs.get(id).copied().unwrap_or(0) as f64 / degree[id] as f64;
+            *degree
+                .keys()
+                .min_by(|a, b| ratio(a).total_cmp(&ratio(b)))
+                .unwrap()
+        });
+        degree.remove(&id);
+        for neighbour in &graph[&id] {
+            if let Some(degree) = degree.get_mut(neighbour) {
+                *degree -= 1;
             }
         }
+        stack.push(id);
     }
 
-    graph
-}
-
-pub fn allocate(tac: &Vec<Instruction>) -> HashMap<u64, RegisterKind> {
-    let lifetimes = scan(tac);
-    let graph = interference_graph(lifetimes);
-    let registers = RegisterKind::allocable();
     let mut allocated: HashMap<u64, RegisterKind> = HashMap::new();
-
-    let mut ids = graph.keys().cloned().collect::<Vec<u64>>();
-    ids.sort_by_key(|id| -(graph.get(id).unwrap().len() as isize));
-    for id in ids.iter() {
-        let mut used = HashSet::new();
-        for overlap in graph.get(&id).unwrap().iter() {
-            if let Some(register) = allocated.get(overlap) {
-                used.insert(register.clone());
+    let mut spilled = Vec::new();
+    while let Some(id) = stack.pop() {
+        let used: HashSet<_> = graph[&id]
+            .iter()
+            .filter_map(|neighbour| allocated.get(neighbour))
+            .collect();
+        match registers.iter().find(|register| !used.contains(register)) {
+            Some(register) => {
+                allocated.insert(id, register.clone());
             }
-        }
-
-        if let Some(register) = registers.iter().find(|register| !used.contains(register)) {
-            allocated.insert(*id, register.clone());
-        } else {
-            panic!("error: all registers have been allocated") // TODO: memory spilling
+            None => spilled.push(id),
         }
     }
+    spilled.sort();
+    (allocated, spilled)
+}
+
+/// Where every temporary lives.
+pub struct Allocation {
+    pub registers: HashMap<u64, RegisterKind>,
+    /// Offsets below `rbp` of temporaries that live on the stack.
+    pub slots: HashMap<u64, u32>,
+}
 
-    allocated
+pub fn allocate(graph: &Graph) -> Allocation {
+    let live_out = liveness(graph);
+    let costs = spill_costs(graph);
+    let graph = interference_graph(graph, &live_out);
+
+    let (registers, spilled) = colour(&graph, &costs, &RegisterKind::allocable());
+    if spilled.is_empty() {
+        return Allocation {
+            registers,
+            slots: HashMap::new(),
+        };
+    }
+    // spilled values pass through the scratch registers, so they cannot hold anything else
+    let available: Vec<_> = RegisterKind::allocable()
+        .into_iter()
+        .filter(|register| !RegisterKind::scratch().contains(register))
+        .collect();
+    let (registers, spilled) = colour(&graph, &costs, &available);
+    let slots = spilled
+        .into_iter()
+        .enumerate()
+        .map(|(index, id)| (id, (index as u32 + 1) * 8))
+        .collect();
+    Allocation { registers, slots }
 }
@@ -0,0 +1,520 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+};
+
+use crate::elf;
+
+const SHT_SYMTAB: u32 = 2;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u64 = 0x1;
+const SHF_ALLOC: u64 = 0x2;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+
+const STB_LOCAL: u8 = 0;
+const STB_WEAK: u8 = 2;
+
+const R_X86_64_64: u32 = 1;
+const R_X86_64_PC32: u32 = 2;
+const R_X86_64_PLT32: u32 = 4;
+const R_X86_64_32: u32 = 10;
+const R_X86_64_32S: u32 = 11;
+
+const PT_LOAD: u32 = 1;
+const PT_GNU_STACK: u32 = 0x6474e551;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+/// Where the executable is loaded. The file is mapped as it is, so an
+/// address is always `BASE` plus its offset in the file.
+const BASE: u64 = 0x400000;
+const PAGE: u64 = 0x1000;
+
+#[derive(Debug)]
+pub enum LinkError {
+    Malformed(&'static str),
+    Undefined(String),
+    Duplicate(String),
+    UnsupportedRelocation(u32),
+    OutOfRange(String),
+}
+
+impl Display for LinkError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Malformed(message) => {
+                write!(f, "malformed object: {message}")
+            }
+            Self::Undefined(name) => {
+                write!(f, "undefined reference to `{name}`")
+            }
+            Self::Duplicate(name) => {
+                write!(f, "multiple definitions of `{name}`")
+            }
+            Self::UnsupportedRelocation(type_) => {
+                write!(f, "unsupported relocation type {type_}")
+            }
+            Self::OutOfRange(name) => {
+                write!(f, "relocation against `{name}` is out of range")
+            }
+        }
+    }
+}
+
+/// `offset + size`, which has to fit in 64 bits.
+fn end_of(offset: u64, size: u64) -> Result<u64, LinkError> {
+    offset
+        .checked_add(size)
+        .ok_or(LinkError::Malformed("offset out of range"))
+}
+
+fn read<const N: usize>(bytes: &[u8], offset: u64) -> Result<[u8; N], LinkError> {
+    let end = end_of(offset, N as u64)?;
+    bytes
+        .get(offset as usize..end as usize)
+        .and_then(|slice| slice.try_into().ok())
+        .ok_or(LinkError::Malformed("truncated file"))
+}
+
+fn u16_at(bytes: &[u8], offset: u64) -> Result<u16, LinkError> {
+    read(bytes, offset).map(u16::from_le_bytes)
+}
+
+fn u32_at(bytes: &[u8], offset: u64) -> Result<u32, LinkError> {
+    read(bytes, offset).map(u32::from_le_bytes)
+}
+
+fn u64_at(bytes: &[u8], offset: u64) -> Result<u64, LinkError> {
+    read(bytes, offset).map(u64::from_le_bytes)
+}
+
+fn name_at(strings: &[u8], offset: u32) -> Result<String, LinkError> {
+    let name = strings
+        .get(offset as usize..)
+        .and_then(|rest| rest.split(|byte| *byte == 0).next())
+        .ok_or(LinkError::Malformed("name outside of its string table"))?;
+    Ok(String::from_utf8_lossy(name).into_owned())
+}
+
+struct Section {
+    type_: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+}
+
+struct Symbol {
+    name: String,
+    bind: u8,
+    section: u16,
+    value: u64,
+}
+
+/// An ELF64 relocatable object for x86-64.
+struct Object<'a> {
+    bytes: &'a [u8],
+    sections: Vec<Section>,
+    symbols: Vec<Symbol>,
+}
+
+impl<'a> Object<'a> {
+    fn parse(bytes: &'a [u8]) -> Result<Self, LinkError> {
+        if bytes.get(..6) != Some(b"\x7fELF\x02\x01") {
+            return Err(LinkError::Malformed("not a little endian ELF64 file"));
+        }
+        if u16_at(bytes, 16)? != 1 || u16_at(bytes, 18)? != 62 {
+            return Err(LinkError::Malformed("not an x86-64 relocatable object"));
+        }
+        let headers = u64_at(bytes, 0x28)?;
+        let count = u16_at(bytes, 0x3c)? as u64;
+        let sections = (0..count)
+            .map(|index| {
+                let header = end_of(headers, index * 64)?;
+                let section = Section {
+                    type_: u32_at(bytes, header + 4)?,
+                    flags: u64_at(bytes, header + 8)?,
+                    offset: u64_at(bytes, header + 24)?,
+                    size: u64_at(bytes, header + 32)?,
+                    link: u32_at(bytes, header + 40)?,
+                    info: u32_at(bytes, header + 44)?,
+                    align: u64_at(bytes, header + 48)?,
+                };
+                // segments are only aligned to a page, so neither is a section
+                if section.align > PAGE || !section.align.is_power_of_two() && section.align != 0 {
+                    return Err(LinkError::Malformed("unsupported section alignment"));
+                }
+                Ok(section)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut object = Self {
+            bytes,
+            sections,
+            symbols: Vec::new(),
+        };
+        let Some(symtab) = object.sections.iter().find(|s| s.type_ == SHT_SYMTAB) else {
+            return Ok(object);
+        };
+        let strings = object.data(symtab.link as usize)?;
+        let mut symbols = Vec::new();
+        for entry in object.data_of(symtab)?.chunks_exact(24) {
+            symbols.push(Symbol {
+                name: name_at(strings, u32_at(entry, 0)?)?,
+                bind: entry[4] >> 4,
+                section: u16_at(entry, 6)?,
+                value: u64_at(entry, 8)?,
+            });
+        }
+        object.symbols = symbols;
+        Ok(object)
+    }
+
+    fn data_of(&self, section: &Section) -> Result<&'a [u8], LinkError> {
+        if section.type_ == SHT_NOBITS {
+            return Ok(&[]);
+        }
+        let end = end_of(section.offset, section.size)?;
+        self.bytes
+            .get(section.offset as usize..end as usize)
+            .ok_or(LinkError::Malformed("section outside of the file"))
+    }
+
+    fn data(&self, index: usize) -> Result<&'a [u8], LinkError> {
+        let section = self
+            .sections
+            .get(index)
+            .ok_or(LinkError::Malformed("no such section"))?;
+        self.data_of(section)
+    }
+}
+
+fn align(value: u64, align: u64) -> Result<u64, LinkError> {
+    value
+        .checked_next_multiple_of(align.max(1))
+        .ok_or(LinkError::Malformed("offset out of range"))
+}
+
+/// Where every loaded section of every object ended up.
+struct Layout {
+    image: Vec<u8>,
+    addresses: Vec<HashMap<usize, u64>>,
+    /// Sizes of the code and data segments in the file and in memory.
+    text: u64,
+    data: Option<(u64, u64, u64)>,
+}
+
+fn layout(objects: &[Object], headers: u64, writable_data: bool) -> Result<Layout, LinkError> {
+    let loaded = |section: &Section| section.flags & SHF_ALLOC != 0;
+    let writable = |section: &Section| section.flags & SHF_WRITE != 0;
+
+    let mut image = vec![0; headers as usize];
+    let mut addresses = vec![HashMap::new(); objects.len()];
+    let mut place = |image: &mut Vec<u8>, filter: &dyn Fn(&Section) -> bool| {
+        for (object, addresses) in objects.iter().zip(&mut addresses) {
+            for (index, section) in object.sections.iter().enumerate() {
+                if loaded(section) && section.type_ != SHT_NOBITS && filter(section) {
+                    image.resize(align(image.len() as u64, section.align)? as usize, 0);
+                    addresses.insert(index, BASE + image.len() as u64);
+                    image.extend_from_slice(object.data_of(section)?);
+                }
+            }
+        }
+        Ok::<_, LinkError>(())
+    };
+
+    // code and constants share a segment, and anything written goes to the
+    // next page so that it can have other permissions
+    place(&mut image, &|section| !writable(section))?;
+    let text = image.len() as u64;
+    let mut data = None;
+    if writable_data {
+        image.resize(align(text, PAGE)? as usize, 0);
+        let start = image.len() as u64;
+        place(&mut image, &writable)?;
+        let mut end = image.len() as u64;
+        for (object, addresses) in objects.iter().zip(&mut addresses) {
+            for (index, section) in object.sections.iter().enumerate() {
+                if loaded(section) && section.type_ == SHT_NOBITS {
+                    end = align(end, section.align)?;
+                    addresses.insert(index, end_of(BASE, end)?);
+                    end = end_of(end, section.size)?;
+                }
+            }
+        }
+        data = Some((start, image.len() as u64 - start, end - start));
+    }
+    Ok(Layout {
+        image,
+        addresses,
+        text,
+        data,
+    })
+}
+
+fn program_header(output: &mut Vec<u8>, type_: u32, flags: u32, segment: (u64, u64, u64)) {
+    let (offset, file, memory) = segment;
+    output.extend_from_slice(&type_.to_le_bytes());
+    output.extend_from_slice(&flags.to_le_bytes());
+    output.extend_from_slice(&offset.to_le_bytes());
+    output.extend_from_slice(&(BASE + offset).to_le_bytes());
+    output.extend_from_slice(&(BASE + offset).to_le_bytes());
+    output.extend_from_slice(&file.to_le_bytes());
+    output.extend_from_slice(&memory.to_le_bytes());
+    output.extend_from_slice(&PAGE.to_le_bytes());
+}
+
+/// Links x86-64 relocatable `objects` into a static executable starting at
+/// `_start`. Every loaded section is kept as it is, without merging or
+/// garbage collection.
+pub fn link(objects: &[&[u8]]) -> Result<Vec<u8>, LinkError> {
+    let objects = objects
+        .iter()
+        .map(|bytes| Object::parse(bytes))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let writable = objects
+        .iter()
+        .flat_map(|object| &object.sections)
+        .any(|section| {
+            section.flags & SHF_ALLOC != 0 && section.flags & SHF_WRITE != 0 && section.size > 0
+        });
+    let segments = if writable { 3 } else { 2 };
+    let headers = 64 + 56 * segments;
+    let Layout {
+        mut image,
+        addresses,
+        text,
+        data,
+    } = layout(&objects, headers, writable)?;
+
+    let address = |object: usize, symbol: &Symbol| match symbol.section {
+        SHN_UNDEF => None,
+        SHN_ABS => Some(symbol.value),
+        section => addresses[object]
+            .get(&(section as usize))
+            .and_then(|address| address.checked_add(symbol.value)),
+    };
+    let mut globals = HashMap::new();
+    let mut weak = HashMap::new();
+    for (index, object) in objects.iter().enumerate() {
+        for symbol in &object.symbols {
+            if symbol.bind == STB_LOCAL {
+                continue;
+            }
+            let Some(address) = address(index, symbol) else {
+                continue;
+            };
+            if symbol.bind == STB_WEAK {
+                weak.entry(symbol.name.clone()).or_insert(address);
+            } else if globals.insert(symbol.name.clone(), address).is_some() {
+                return Err(LinkError::Duplicate(symbol.name.clone()));
+            }
+        }
+    }
+    for (name, address) in weak {
+        globals.entry(name).or_insert(address);
+    }
+    let resolve = |object: usize, symbol: &Symbol| match symbol.section {
+        SHN_UNDEF => globals
+            .get(&symbol.name)
+            .copied()
+            .ok_or_else(|| LinkError::Undefined(symbol.name.clone())),
+        _ => address(object, symbol).ok_or(LinkError::Malformed("symbol in an unloaded section")),
+    };
+
+    for (index, object) in objects.iter().enumerate() {
+        for section in object.sections.iter().filter(|s| s.type_ == SHT_RELA) {
+            // relocations of debug information and the like are not needed
+            let Some(target) = addresses[index].get(&(section.info as usize)) else {
+                continue;
+            };
+            for entry in object.data_of(section)?.chunks_exact(24) {
+                let offset = u64_at(entry, 0)?;
+                let info = u64_at(entry, 8)?;
+                let addend = u64_at(entry, 16)? as i64;
+                let symbol = object
+                    .symbols
+                    .get((info >> 32) as usize)
+                    .ok_or(LinkError::Malformed("no such symbol"))?;
+                let place = end_of(*target, offset)?;
+                let value = resolve(index, symbol)?.wrapping_add_signed(addend);
+                let out_of_range = || LinkError::OutOfRange(symbol.name.clone());
+                let bytes = match info as u32 {
+                    R_X86_64_64 => value.to_le_bytes().to_vec(),
+                    R_X86_64_PC32 | R_X86_64_PLT32 => {
+                        let relative = value.wrapping_sub(place) as i64;
+                        let relative = i32::try_from(relative).map_err(|_| out_of_range())?;
+                        relative.to_le_bytes().to_vec()
+                    }
+                    R_X86_64_32 => {
+                        let value = u32::try_from(value).map_err(|_| out_of_range())?;
+                        value.to_le_bytes().to_vec()
+                    }
+                    R_X86_64_32S => {
+                        let value = i32::try_from(value as i64).map_err(|_| out_of_range())?;
+                        value.to_le_bytes().to_vec()
+                    }
+                    type_ => return Err(LinkError::UnsupportedRelocation(type_)),
+                };
+                let start = place - BASE;
+                let end = end_of(start, bytes.len() as u64)?;
+                image
+                    .get_mut(start as usize..end as usize)
+                    .ok_or(LinkError::Malformed("relocation outside of its section"))?
+                    .copy_from_slice(&bytes);
+            }
+        }
+    }
+
+    let entry = *globals
+        .get("_start")
+        .ok_or_else(|| LinkError::Undefined("_start".into()))?;
+    let header = elf::header(2, entry, segments as u16, 0, 0, 0);
+    image[..64].copy_from_slice(&header);
+    let mut program_headers = Vec::new();
+    program_header(&mut program_headers, PT_LOAD, PF_R | PF_X, (0, text, text));
+    if let Some(data) = data {
+        program_header(&mut program_headers, PT_LOAD, PF_R | PF_W, data);
+    }
+    // marks the stack as not executable
+    program_header(&mut program_headers, PT_GNU_STACK, PF_R | PF_W, (0, 0, 0));
+    image[64..headers as usize].copy_from_slice(&program_headers);
+    Ok(image)
+}
+
+#[cfg(test)]
+mod tests {
+    use mellow_parse::Identifier;
+
+    use super::*;
+    use crate::{
+        assembly::Assembly,
+        data::Data,
+        encode::encode,
+        register::{Register, RegisterKind, Size},
+    };
+
+    fn object(text: &[Assembly]) -> Vec<u8> {
+        elf::write(encode(text), &[Assembly::String(0, "hi".into())])
+    }
+
+    /// `object` with its `_start` called `name`, which has the same length.
+    fn renamed(mut object: Vec<u8>, name: &[u8; 6]) -> Vec<u8> {
+        let at = object
+            .windows(8)
+            .position(|window| window == b"\0_start\0")
+            .unwrap();
+        object[at + 1..at + 7].copy_from_slice(name);
+        object
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn i32_at(bytes: &[u8], offset: usize) -> i32 {
+        i32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn executable() {
+        let rbx = Data::Register(Register::new(RegisterKind::B, Size::Qword));
+        let main = object(&[
+            Assembly::Call(Identifier::from("helper")),
+            Assembly::Mov(rbx, Data::String(0)),
+        ]);
+        let runtime = renamed(object(&[Assembly::Syscall]), b"helper");
+        let executable = link(&[&main, &runtime]).unwrap();
+
+        assert_eq!(&executable[..4], b"\x7fELF");
+        assert_eq!(u16::from_le_bytes([executable[16], executable[17]]), 2);
+        // nothing is writable, so code and the stack are the only segments
+        assert_eq!(u16::from_le_bytes([executable[56], executable[57]]), 2);
+
+        let start = (u64_at(&executable, 24) - BASE) as usize;
+        let call = start + 5 + i32_at(&executable, start + 1) as usize;
+        assert_eq!(executable[call..call + 2], [0x0f, 0x05]);
+        // `lea rbx, [rip + _s0]`
+        let string = start + 12 + i32_at(&executable, start + 8) as usize;
+        assert_eq!(&executable[string..string + 3], b"hi\0");
+    }
+
+    #[test]
+    fn undefined() {
+        let main = object(&[Assembly::Call(Identifier::from("helper"))]);
+        assert!(matches!(
+            link(&[&main]),
+            Err(LinkError::Undefined(name)) if name == "helper"
+        ));
+        let runtime = renamed(object(&[Assembly::Syscall]), b"helper");
+        assert!(matches!(
+            link(&[&runtime]),
+            Err(LinkError::Undefined(name)) if name == "_start"
+        ));
+    }
+
+    #[test]
+    fn duplicate() {
+        let main = object(&[Assembly::Syscall]);
+        assert!(matches!(
+            link(&[&main, &main]),
+            Err(LinkError::Duplicate(name)) if name == "_start"
+        ));
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(matches!(
+            link(&[b"not an object"]),
+            Err(LinkError::Malformed(_))
+        ));
+        let main = object(&[Assembly::Syscall]);
+        assert!(matches!(
+            link(&[&main[..100]]),
+            Err(LinkError::Malformed(_))
+        ));
+    }
+
+    #[test]
+    fn malformed_section_headers() {
+        let main = object(&[Assembly::Syscall]);
+        // `.text` is section 1 and `.rodata` section 2
+        let header = |index: u64| (u64_at(&main, 0x28) + index * 64) as usize;
+        let patched = |index: u64, fields: &[(usize, u64)]| {
+            let mut object = main.clone();
+            for (field, value) in fields {
+                let at = header(index) + field;
+                object[at..at + 8].copy_from_slice(&value.to_le_bytes());
+            }
+            object
+        };
+        let objects = [
+            // `sh_addralign` that is not a power of two, or over a page
+            patched(1, &[(48, 3)]),
+            patched(1, &[(48, 1 << 20)]),
+            // `sh_offset` and `sh_size` that overflow when added
+            patched(1, &[(24, u64::MAX - 1)]),
+            patched(1, &[(32, u64::MAX)]),
+            // `.bss` that overflows the end of the address space
+            patched(
+                2,
+                &[
+                    (4, SHT_NOBITS as u64),
+                    (8, SHF_ALLOC | SHF_WRITE),
+                    (32, u64::MAX),
+                ],
+            ),
+        ];
+        for object in objects {
+            assert!(matches!(link(&[&object]), Err(LinkError::Malformed(_))));
+        }
+    }
+}
@@ -1,51 +1,493 @@
-use std::{iter::Peekable, slice::Iter};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display, Formatter},
+};
 
-use crate::assembly::Assembly;
+use crate::{
+    assembly::Assembly,
+    data::Data,
+    register::{RegisterKind, Size},
+};
 
 impl Assembly {
-    pub fn optimize(self) -> Self {
-        match &self {
-            Self::Mov(to, from) => {
-                if to == from {
-                    return Self::Empty;
-                }
+    fn target(&self) -> Option<u64> {
+        match self {
+            Self::Jmp(label)
+            | Self::Je(label)
+            | Self::Jne(label)
+            | Self::Jg(label)
+            | Self::Jl(label)
+            | Self::Jge(label)
+            | Self::Jle(label) => Some(*label),
+            _ => None,
+        }
+    }
+
+    fn target_mut(&mut self) -> Option<&mut u64> {
+        match self {
+            Self::Jmp(label)
+            | Self::Je(label)
+            | Self::Jne(label)
+            | Self::Jg(label)
+            | Self::Jl(label)
+            | Self::Jge(label)
+            | Self::Jle(label) => Some(label),
+            _ => None,
+        }
+    }
+
+    fn reads_flags(&self) -> bool {
+        matches!(
+            self,
+            Self::Sete(_)
+                | Self::Setg(_)
+                | Self::Setl(_)
+                | Self::Je(_)
+                | Self::Jne(_)
+                | Self::Jg(_)
+                | Self::Jl(_)
+                | Self::Jge(_)
+                | Self::Jle(_)
+        )
+    }
+
+    fn writes_flags(&self) -> bool {
+        matches!(
+            self,
+            Self::Cmp(..)
+                | Self::Test(..)
+                | Self::Add(..)
+                | Self::Sub(..)
+                | Self::Xor(..)
+                | Self::Imul(..)
+                | Self::Idiv(_)
+                | Self::Shl(..)
+                | Self::Shr(..)
+                | Self::Sar(..)
+        )
+    }
+
+    /// Whether this replaces the whole of `kind` without looking at it.
+    fn overwrites(&self, kind: &RegisterKind) -> bool {
+        match self {
+            Self::Mov(Data::Register(register), from)
+            | Self::Lea(Data::Register(register), from) => {
+                register.kind() == kind
+                    && matches!(register.size(), Size::Dword | Size::Qword)
+                    && !from.reads(kind)
+            }
+            Self::Xor(Data::Register(register), other) => {
+                register.kind() == kind && other == &Data::Register(register.clone())
             }
-            _ => {}
+            Self::Pop(Data::Register(register)) => register.kind() == kind,
+            _ => false,
         }
-        self
     }
+}
 
-    pub fn optimize_with(self, source: &mut Peekable<Iter<Self>>) -> Self {
-        match &self {
-            Self::Mov(to, from) => {
-                if let Some(Self::Mov(next_to, next_from)) = source.peek() {
-                    if to == next_from {
-                        source.next();
-                        return Self::Mov(next_to.clone(), from.clone());
-                    }
-                }
+impl Data {
+    fn reads(&self, kind: &RegisterKind) -> bool {
+        match self {
+            Self::Register(register) => register.kind() == kind,
+            Self::Stack(_) => kind == &RegisterKind::Bp,
+            Self::Address { base, index, .. } => base
+                .iter()
+                .chain(index.iter().map(|(index, _)| index))
+                .any(|register| register.kind() == kind),
+            Self::Integer(_) | Self::String(_) => false,
+        }
+    }
+}
+
+/// What rules know about the whole program, gathered before every pass.
+struct Context {
+    /// Labels some jump goes to.
+    referenced: HashSet<u64>,
+    /// Labels that are only a jump to somewhere else, and where that chain of
+    /// jumps ends.
+    forwarded: HashMap<u64, u64>,
+}
+
+impl Context {
+    fn new(assembly: &[Assembly]) -> Self {
+        let referenced = assembly.iter().filter_map(Assembly::target).collect();
+
+        let mut jumps = HashMap::new();
+        for (position, instruction) in assembly.iter().enumerate() {
+            if let Assembly::Label(label) = instruction
+                && let Some(Assembly::Jmp(to)) = assembly[position + 1..]
+                    .iter()
+                    .find(|instruction| !matches!(instruction, Assembly::Label(_)))
+            {
+                jumps.insert(*label, *to);
+            }
+        }
+        // every chain is followed once, and all labels on it learn where it ends
+        let mut forwarded = HashMap::new();
+        let mut resolved = HashSet::new();
+        for label in jumps.keys() {
+            let mut chain = Vec::new();
+            let mut seen = HashSet::new();
+            let mut to = *label;
+            while let Some(next) = jumps.get(&to)
+                && !resolved.contains(&to)
+                && seen.insert(to)
+            {
+                chain.push(to);
+                to = *next;
             }
-            Self::Jmp(label) => {
-                if let Some(Self::Label(id)) = source.peek() {
-                    if label == id {
-                        return Self::Empty;
-                    }
+            let end = match forwarded.get(&to) {
+                Some(end) => Some(*end),
+                // jumps that go around in circles stay as they are
+                None if seen.contains(&to) || resolved.contains(&to) => None,
+                None => Some(to),
+            };
+            for label in chain {
+                resolved.insert(label);
+                if let Some(end) = end {
+                    forwarded.insert(label, end);
                 }
             }
-            _ => {}
         }
-        self
+
+        Self {
+            referenced,
+            forwarded,
+        }
+    }
+}
+
+/// A rewrite of the first `window` instructions of the code it is given,
+/// which is everything from the current position on.
+struct Rule {
+    name: &'static str,
+    window: usize,
+    apply: fn(&[Assembly], &Context) -> Option<Vec<Assembly>>,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        name: "self move",
+        window: 1,
+        apply: self_move,
+    },
+    Rule {
+        name: "jump to next",
+        window: 2,
+        apply: jump_to_next,
+    },
+    Rule {
+        name: "jump threading",
+        window: 1,
+        apply: thread,
+    },
+    Rule {
+        name: "unused label",
+        window: 1,
+        apply: unused_label,
+    },
+    Rule {
+        name: "dead store",
+        window: 2,
+        apply: dead_store,
+    },
+    Rule {
+        name: "zero with xor",
+        window: 1,
+        apply: zero,
+    },
+    Rule {
+        name: "compare with test",
+        window: 1,
+        apply: test,
+    },
+];
+
+fn self_move(code: &[Assembly], _context: &Context) -> Option<Vec<Assembly>> {
+    match &code[0] {
+        Assembly::Mov(to, from) if to == from => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+fn jump_to_next(code: &[Assembly], _context: &Context) -> Option<Vec<Assembly>> {
+    match &code[..2] {
+        [Assembly::Jmp(to), Assembly::Label(label)] if to == label => Some(vec![code[1].clone()]),
+        _ => None,
     }
 }
 
-pub fn optimize(assembly: Vec<Assembly>) -> Vec<Assembly> {
-    let mut output = Vec::new();
-    let mut assembly = assembly.iter().peekable();
-    while let Some(instruction) = assembly.next() {
-        let instruction = instruction.clone().optimize_with(&mut assembly).optimize();
-        if instruction != Assembly::Empty {
-            output.push(instruction);
+fn thread(code: &[Assembly], context: &Context) -> Option<Vec<Assembly>> {
+    let mut jump = code[0].clone();
+    let to = jump.target_mut()?;
+    *to = *context.forwarded.get(to)?;
+    Some(vec![jump])
+}
+
+fn unused_label(code: &[Assembly], context: &Context) -> Option<Vec<Assembly>> {
+    match &code[0] {
+        Assembly::Label(label) if !context.referenced.contains(label) => Some(Vec::new()),
+        _ => None,
+    }
+}
+
+/// A register written and then written again before anything reads it.
+fn dead_store(code: &[Assembly], _context: &Context) -> Option<Vec<Assembly>> {
+    let (Assembly::Mov(Data::Register(register), _) | Assembly::Lea(Data::Register(register), _)) =
+        &code[0]
+    else {
+        return None;
+    };
+    if matches!(register.kind(), RegisterKind::Sp | RegisterKind::Bp) {
+        return None;
+    }
+    code[1]
+        .overwrites(register.kind())
+        .then(|| vec![code[1].clone()])
+}
+
+/// `xor` is shorter, but it also sets flags, so nothing may read them after.
+fn zero(code: &[Assembly], _context: &Context) -> Option<Vec<Assembly>> {
+    let Assembly::Mov(Data::Register(register), Data::Integer(0)) = &code[0] else {
+        return None;
+    };
+    for instruction in &code[1..] {
+        if instruction.reads_flags() {
+            return None;
+        }
+        if instruction.writes_flags() || matches!(instruction, Assembly::Call(_)) {
+            break;
         }
+        // flags may be read wherever control goes next
+        if matches!(instruction, Assembly::Label(_) | Assembly::Jmp(_)) {
+            return None;
+        }
+    }
+    let register = Data::Register(register.resize(Size::Dword));
+    Some(vec![Assembly::Xor(register.clone(), register)])
+}
+
+fn test(code: &[Assembly], _context: &Context) -> Option<Vec<Assembly>> {
+    match &code[0] {
+        Assembly::Cmp(register @ Data::Register(_), Data::Integer(0)) => {
+            Some(vec![Assembly::Test(register.clone(), register.clone())])
+        }
+        _ => None,
+    }
+}
+
+/// How many times each peephole rule fired.
+pub struct Statistics {
+    fired: Vec<usize>,
+}
+
+impl Display for Statistics {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (rule, count) in RULES.iter().zip(&self.fired) {
+            if *count > 0 {
+                writeln!(f, "peephole: {}: {count}", rule.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the first matching rule at every position. Replacements are looked
+/// at again by the next pass.
+fn pass(assembly: &[Assembly], fired: &mut [usize]) -> Option<Vec<Assembly>> {
+    let context = Context::new(assembly);
+    let mut output = Vec::with_capacity(assembly.len());
+    let mut changed = false;
+    let mut position = 0;
+    while position < assembly.len() {
+        let code = &assembly[position..];
+        let rewrite = RULES.iter().enumerate().find_map(|(index, rule)| {
+            if code.len() < rule.window {
+                return None;
+            }
+            (rule.apply)(code, &context).map(|replacement| (index, rule.window, replacement))
+        });
+        match rewrite {
+            Some((index, window, replacement)) => {
+                fired[index] += 1;
+                changed = true;
+                output.extend(replacement);
+                position += window;
+            }
+            None => {
+                output.push(code[0].clone());
+                position += 1;
+            }
+        }
+    }
+    changed.then_some(output)
+}
+
+/// Applies the peephole rules until none of them fires.
+pub fn optimize(mut assembly: Vec<Assembly>) -> (Vec<Assembly>, Statistics) {
+    let mut fired = vec![0; RULES.len()];
+    while let Some(output) = pass(&assembly, &mut fired) {
+        assembly = output;
+    }
+    (assembly, Statistics { fired })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::register::Register;
+
+    fn register(kind: RegisterKind) -> Data {
+        Data::Register(Register::new(kind, Size::Qword))
+    }
+
+    fn run(assembly: Vec<Assembly>) -> (Vec<Assembly>, String) {
+        let (assembly, statistics) = optimize(assembly);
+        (assembly, statistics.to_string())
+    }
+
+    #[test]
+    fn self_move() {
+        let rbx = register(RegisterKind::B);
+        let (assembly, statistics) = run(vec![
+            Assembly::Mov(rbx.clone(), rbx.clone()),
+            Assembly::Push(rbx.clone()),
+        ]);
+        assert_eq!(assembly, vec![Assembly::Push(rbx)]);
+        assert_eq!(statistics, "peephole: self move: 1\n");
+    }
+
+    #[test]
+    fn jump_to_next() {
+        let (assembly, statistics) =
+            run(vec![Assembly::Jmp(1), Assembly::Label(1), Assembly::Jmp(1)]);
+        assert_eq!(assembly, vec![Assembly::Label(1), Assembly::Jmp(1)]);
+        assert_eq!(statistics, "peephole: jump to next: 1\n");
+    }
+
+    #[test]
+    fn jump_threading() {
+        let rbx = register(RegisterKind::B);
+        let (assembly, statistics) = run(vec![
+            Assembly::Je(1),
+            Assembly::Push(rbx.clone()),
+            Assembly::Label(1),
+            Assembly::Jmp(2),
+            Assembly::Push(rbx.clone()),
+            Assembly::Label(2),
+            Assembly::Pop(rbx.clone()),
+        ]);
+        assert_eq!(
+            assembly,
+            vec![
+                Assembly::Je(2),
+                Assembly::Push(rbx.clone()),
+                Assembly::Jmp(2),
+                Assembly::Push(rbx.clone()),
+                Assembly::Label(2),
+                Assembly::Pop(rbx),
+            ]
+        );
+        assert_eq!(
+            statistics,
+            "peephole: jump threading: 1\npeephole: unused label: 1\n"
+        );
+    }
+
+    #[test]
+    fn jump_cycles_stay() {
+        let rbx = register(RegisterKind::B);
+        let assembly = vec![
+            Assembly::Label(1),
+            Assembly::Jmp(2),
+            Assembly::Push(rbx),
+            Assembly::Label(2),
+            Assembly::Jmp(1),
+        ];
+        let (output, statistics) = run(assembly.clone());
+        assert_eq!(output, assembly);
+        assert_eq!(statistics, "");
+    }
+
+    #[test]
+    fn dead_store() {
+        let rbx = register(RegisterKind::B);
+        let (assembly, statistics) = run(vec![
+            Assembly::Mov(rbx.clone(), Data::Integer(1)),
+            Assembly::Mov(rbx.clone(), Data::Integer(2)),
+            Assembly::Push(rbx.clone()),
+        ]);
+        assert_eq!(
+            assembly,
+            vec![
+                Assembly::Mov(rbx.clone(), Data::Integer(2)),
+                Assembly::Push(rbx)
+            ]
+        );
+        assert_eq!(statistics, "peephole: dead store: 1\n");
+    }
+
+    #[test]
+    fn store_read_by_the_next_write_stays() {
+        let rbx = register(RegisterKind::B);
+        let assembly = vec![
+            Assembly::Mov(rbx.clone(), Data::Integer(1)),
+            Assembly::Lea(
+                rbx.clone(),
+                Data::Address {
+                    base: Some(Register::new(RegisterKind::B, Size::Qword)),
+                    index: None,
+                    displacement: 8,
+                },
+            ),
+            Assembly::Push(rbx),
+        ];
+        let (output, _) = run(assembly.clone());
+        assert_eq!(output, assembly);
+    }
+
+    #[test]
+    fn zero_with_xor() {
+        let rbx = register(RegisterKind::B);
+        let ebx = Data::Register(Register::new(RegisterKind::B, Size::Dword));
+        let (assembly, statistics) = run(vec![
+            Assembly::Mov(rbx.clone(), Data::Integer(0)),
+            Assembly::Push(rbx.clone()),
+        ]);
+        assert_eq!(
+            assembly,
+            vec![Assembly::Xor(ebx.clone(), ebx), Assembly::Push(rbx)]
+        );
+        assert_eq!(statistics, "peephole: zero with xor: 1\n");
+    }
+
+    #[test]
+    fn zero_before_a_flag_reader_stays() {
+        let rbx = register(RegisterKind::B);
+        let rcx = register(RegisterKind::C);
+        let assembly = vec![
+            Assembly::Cmp(rcx, Data::Integer(3)),
+            Assembly::Mov(rbx.clone(), Data::Integer(0)),
+            Assembly::Je(1),
+            Assembly::Push(rbx),
+            Assembly::Label(1),
+            Assembly::Jmp(1),
+        ];
+        let (output, statistics) = run(assembly.clone());
+        assert_eq!(output, assembly);
+        assert_eq!(statistics, "");
+    }
+
+    #[test]
+    fn compare_with_test() {
+        let rbx = register(RegisterKind::B);
+        let (assembly, statistics) = run(vec![
+            Assembly::Cmp(rbx.clone(), Data::Integer(0)),
+            Assembly::Je(1),
+            Assembly::Label(1),
+            Assembly::Jmp(1),
+        ]);
+        assert_eq!(assembly[0], Assembly::Test(rbx.clone(), rbx));
+        assert_eq!(statistics, "peephole: compare with test: 1\n");
     }
-    output
 }
@@ -25,6 +25,41 @@ impl RegisterKind {
         use RegisterKind::*;
         vec![B, C, Si, Di, R8, R9, R10, R11, R12, R13, R14, R15]
     }
+
+    /// Registers that carry spilled values in and out of memory. They are
+    /// only taken away from the allocator when something is spilled.
+    pub fn scratch() -> [Self; 2] {
+        [Self::R15, Self::R14]
+    }
+
+    /// The number of the register in ModRM, SIB and REX fields.
+    pub fn number(&self) -> u8 {
+        use RegisterKind::*;
+        match self {
+            A => 0,
+            C => 1,
+            D => 2,
+            B => 3,
+            Sp => 4,
+            Bp => 5,
+            Si => 6,
+            Di => 7,
+            R8 => 8,
+            R9 => 9,
+            R10 => 10,
+            R11 => 11,
+            R12 => 12,
+            R13 => 13,
+            R14 => 14,
+            R15 => 15,
+        }
+    }
+
+    /// Registers that a System V callee is free to overwrite.
+    pub fn is_caller_saved(&self) -> bool {
+        use RegisterKind::*;
+        matches!(self, A | C | D | Si | Di | R8 | R9 | R10 | R11)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -45,6 +80,19 @@ impl Register {
     pub fn new(kind: RegisterKind, size: Size) -> Self {
         Self { kind, size }
     }
+
+    pub fn kind(&self) -> &RegisterKind {
+        &self.kind
+    }
+
+    pub fn size(&self) -> &Size {
+        &self.size
+    }
+
+    /// The same register accessed as `size`.
+    pub fn resize(&self, size: Size) -> Self {
+        Self::new(self.kind.clone(), size)
+    }
 }
 
 impl Display for Register {
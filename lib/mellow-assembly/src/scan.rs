@@ -0,0 +1,123 @@
+use std::collections::{BTreeSet, HashMap};
+
+use ir::cfg::Graph;
+
+use crate::{
+    lifetime::{Allocation, liveness},
+    register::RegisterKind,
+};
+
+/// Positions where a temporary is live, with each block's instructions and
+/// terminator numbered in layout order. It is a single range, so a value
+/// live on only part of a loop is treated as live across all of it.
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    id: u64,
+    start: usize,
+    end: usize,
+}
+
+fn intervals(graph: &Graph) -> Vec<Interval> {
+    let live_out = liveness(graph);
+    let mut ranges: HashMap<u64, (usize, usize)> = HashMap::new();
+    let mut extend = |id: u64, position: usize| {
+        let range = ranges.entry(id).or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+
+    let mut position = 0;
+    for (id, block) in graph.blocks.iter().enumerate() {
+        let start = position;
+        let end = start + block.body.len();
+        let mut live = live_out[id].clone();
+        for temporary in live.iter() {
+            extend(*temporary, end);
+        }
+        for operand in block.terminator.operands() {
+            extend(operand, end);
+            live.insert(operand);
+        }
+        for (index, instruction) in block.body.iter().enumerate().rev() {
+            let here = start + index;
+            if let Some(to) = instruction.result() {
+                extend(to, here);
+                live.remove(&to);
+            }
+            for operand in instruction.operands() {
+                extend(operand, here);
+                live.insert(operand);
+            }
+        }
+        // what is still live came in from another block
+        for temporary in live {
+            extend(temporary, start);
+        }
+        position = end + 1;
+    }
+
+    let mut output: Vec<_> = ranges
+        .into_iter()
+        .map(|(id, (start, end))| Interval { id, start, end })
+        .collect();
+    output.sort_by_key(|interval| (interval.start, interval.id));
+    output
+}
+
+fn scan(intervals: &[Interval], registers: &[RegisterKind]) -> (HashMap<u64, usize>, Vec<u64>) {
+    let mut free: BTreeSet<usize> = (0..registers.len()).collect();
+    let mut active: BTreeSet<(usize, u64)> = BTreeSet::new();
+    let mut allocated: HashMap<u64, usize> = HashMap::new();
+    let mut spilled = Vec::new();
+
+    for interval in intervals {
+        // a value read where this one is written can hand over its register
+        while let Some(&(end, id)) = active.first()
+            && end <= interval.start
+        {
+            active.pop_first();
+            free.insert(allocated[&id]);
+        }
+        if let Some(register) = free.pop_first() {
+            allocated.insert(interval.id, register);
+            active.insert((interval.end, interval.id));
+            continue;
+        }
+        // spill whichever lives longest, freeing its register for the rest
+        let &(end, id) = active.last().unwrap();
+        if end > interval.end {
+            active.pop_last();
+            let register = allocated.remove(&id).unwrap();
+            spilled.push(id);
+            allocated.insert(interval.id, register);
+            active.insert((interval.end, interval.id));
+        } else {
+            spilled.push(interval.id);
+        }
+    }
+    spilled.sort();
+    (allocated, spilled)
+}
+
+/// Linear-scan allocation over sorted live intervals, which takes
+/// near-linear time but gives worse results than colouring.
+pub fn allocate(graph: &Graph) -> Allocation {
+    let intervals = intervals(graph);
+    let mut registers = RegisterKind::allocable();
+    let (mut allocated, mut spilled) = scan(&intervals, &registers);
+    if !spilled.is_empty() {
+        registers.retain(|register| !RegisterKind::scratch().contains(register));
+        (allocated, spilled) = scan(&intervals, &registers);
+    }
+    Allocation {
+        registers: allocated
+            .into_iter()
+            .map(|(id, register)| (id, registers[register].clone()))
+            .collect(),
+        slots: spilled
+            .into_iter()
+            .enumerate()
+            .map(|(index, id)| (id, (index as u32 + 1) * 8))
+            .collect(),
+    }
+}
@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+
+use ir::tac::Instruction;
+
+use crate::assembly::Assembly;
+
+/// Decisions made on the linear TAC before any code is emitted.
+#[derive(Default)]
+pub struct Selection {
+    /// Integer temporaries folded into every instruction that reads them.
+    pub immediates: HashMap<u64, i128>,
+    /// Conditional jumps replacing comparisons, by position.
+    pub jumps: HashMap<usize, Assembly>,
+    /// `(index, scale)` of multiplications folded into the addition reading
+    /// their result.
+    pub scaled: HashMap<u64, (u64, u8)>,
+    /// Positions of instructions that emit nothing.
+    pub replaced: HashSet<usize>,
+}
+
+/// `log2(value)` if `value` is a positive power of two.
+pub fn power_of_two(value: i128) -> Option<u32> {
+    (value > 0 && value.count_ones() == 1).then(|| value.trailing_zeros())
+}
+
+fn uses(tac: &[Instruction]) -> HashMap<u64, usize> {
+    let mut uses = HashMap::new();
+    for instruction in tac {
+        for operand in instruction.operands() {
+            *uses.entry(operand).or_insert(0) += 1;
+        }
+    }
+    uses
+}
+
+/// Integer temporaries defined once with a value that fits an imm32, negated
+/// or not.
+fn constants(tac: &[Instruction]) -> HashMap<u64, i128> {
+    let mut definitions = HashMap::new();
+    for instruction in tac {
+        if let Some(to) = instruction.result() {
+            *definitions.entry(to).or_insert(0) += 1;
+        }
+    }
+    tac.iter()
+        .filter_map(|instruction| match instruction {
+            Instruction::Integer { to, value }
+                if definitions[to] == 1 && value.abs() <= i32::MAX as i128 =>
+            {
+                Some((*to, *value))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/// Moves constants to the right of commutative operations and comparisons,
+/// where x86 takes immediates.
+fn canonicalize(tac: &mut [Instruction], constants: &HashMap<u64, i128>) {
+    let constant =
+        |left: &u64, right: &u64| constants.contains_key(left) && !constants.contains_key(right);
+    for instruction in tac {
+        let swapped = match *instruction {
+            Instruction::Add { to, left, right } if constant(&left, &right) => Instruction::Add {
+                to,
+                left: right,
+                right: left,
+            },
+            Instruction::Multiply { to, left, right } if constant(&left, &right) => {
+                Instruction::Multiply {
+                    to,
+                    left: right,
+                    right: left,
+                }
+            }
+            Instruction::Equal { to, left, right } if constant(&left, &right) => {
+                Instruction::Equal {
+                    to,
+                    left: right,
+                    right: left,
+                }
+            }
+            Instruction::Greater { to, left, right } if constant(&left, &right) => {
+                Instruction::Less {
+                    to,
+                    left: right,
+                    right: left,
+                }
+            }
+            Instruction::Less { to, left, right } if constant(&left, &right) => {
+                Instruction::Greater {
+                    to,
+                    left: right,
+                    right: left,
+                }
+            }
+            _ => continue,
+        };
+        *instruction = swapped;
+    }
+}
+
+/// Operands of `instruction` that must be in a register.
+fn fixed(instruction: &Instruction, constants: &HashMap<u64, i128>) -> Vec<u64> {
+    match instruction {
+        Instruction::Add { left, .. }
+        | Instruction::Subtract { left, .. }
+        | Instruction::Multiply { left, .. }
+        | Instruction::Greater { left, .. }
+        | Instruction::Less { left, .. }
+        | Instruction::Equal { left, .. } => vec![*left],
+        Instruction::Divide { left, right, .. }
+            if constants
+                .get(right)
+                .is_some_and(|value| power_of_two(*value).is_some()) =>
+        {
+            vec![*left]
+        }
+        Instruction::Power { .. } | Instruction::Set { .. } | Instruction::Call { .. } => {
+            Vec::new()
+        }
+        _ => instruction.operands(),
+    }
+}
+
+/// The jump taken when `comparison` holds, or fails if `negated`.
+fn jump(comparison: &Instruction, negated: bool, to: u64) -> Option<Assembly> {
+    let jump = match (comparison, negated) {
+        (Instruction::Greater { .. }, false) => Assembly::Jg(to),
+        (Instruction::Greater { .. }, true) => Assembly::Jle(to),
+        (Instruction::Less { .. }, false) => Assembly::Jl(to),
+        (Instruction::Less { .. }, true) => Assembly::Jge(to),
+        (Instruction::Equal { .. }, false) => Assembly::Je(to),
+        (Instruction::Equal { .. }, true) => Assembly::Jne(to),
+        _ => return None,
+    };
+    Some(jump)
+}
+
+impl Selection {
+    /// Finds comparisons whose only use is the branch right after them, so
+    /// they can become a `cmp` and a conditional jump without a boolean in
+    /// between.
+    fn fuse(&mut self, tac: &[Instruction], uses: &HashMap<u64, usize>) {
+        for (position, pair) in tac.windows(2).enumerate() {
+            let [comparison, Instruction::JumpIf { condition, to }] = pair else {
+                continue;
+            };
+            if comparison.result() != Some(*condition)
+                || uses[condition] != 1
+                || jump(comparison, false, *to).is_none()
+            {
+                continue;
+            }
+            // when the true target comes next, jump away on the opposite instead
+            let (negated, to) = match (tac.get(position + 2), tac.get(position + 3)) {
+                (Some(Instruction::Jump(false_)), Some(Instruction::Label(next))) if next == to => {
+                    self.replaced.insert(position + 2);
+                    (true, *false_)
+                }
+                _ => (false, *to),
+            };
+            self.jumps
+                .insert(position, jump(comparison, negated, to).unwrap());
+            self.replaced.insert(position + 1);
+        }
+    }
+
+    /// Finds multiplications by 2, 4 or 8 read only by the addition right
+    /// after them, which a single `lea` can do. Only folded integers may come
+    /// in between, since nothing else may take the register of the index.
+    /// Spilled indices are left alone for the same reason.
+    fn scale(
+        &mut self,
+        tac: &[Instruction],
+        uses: &HashMap<u64, usize>,
+        slots: &HashMap<u64, u32>,
+    ) {
+        for (position, instruction) in tac.iter().enumerate() {
+            let Instruction::Multiply {
+                to: product,
+                left: index,
+                right: factor,
+            } = instruction
+            else {
+                continue;
+            };
+            let next = tac[position + 1..]
+                .iter()
+                .zip(position + 1..)
+                .find(|(_, position)| !self.replaced.contains(position));
+            let Some((Instruction::Add { left, right, .. }, _)) = next else {
+                continue;
+            };
+            let Some(scale) = self.immediates.get(factor) else {
+                continue;
+            };
+            if !matches!(scale, 2 | 4 | 8)
+                || uses[product] != 1
+                || (left == product) == (right == product)
+                || self.immediates.contains_key(index)
+                || slots.contains_key(index)
+            {
+                continue;
+            }
+            self.scaled.insert(*product, (*index, *scale as u8));
+            self.replaced.insert(position);
+        }
+    }
+}
+
+pub fn select(tac: &mut [Instruction], slots: &HashMap<u64, u32>) -> Selection {
+    let uses = uses(tac);
+    let constants = constants(tac);
+    canonicalize(tac, &constants);
+
+    let mut immediates = constants.clone();
+    for instruction in tac.iter() {
+        for operand in fixed(instruction, &constants) {
+            immediates.remove(&operand);
+        }
+    }
+
+    let mut selection = Selection {
+        immediates,
+        ..Selection::default()
+    };
+    for (position, instruction) in tac.iter().enumerate() {
+        if let Instruction::Integer { to, .. } = instruction
+            && selection.immediates.contains_key(to)
+        {
+            selection.replaced.insert(position);
+        }
+    }
+    selection.fuse(tac, &uses);
+    selection.scale(tac, &uses, slots);
+    selection
+}
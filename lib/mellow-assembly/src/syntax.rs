@@ -0,0 +1,21 @@
+/// The assembler dialect code is printed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Nasm,
+    /// GNU `as` with `.intel_syntax noprefix`.
+    Gas,
+}
+
+impl Syntax {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "nasm" => Some(Self::Nasm),
+            "gas" => Some(Self::Gas),
+            _ => None,
+        }
+    }
+}
+
+/// `value` printed in a syntax. `Display` of the value itself is NASM.
+pub struct Spelled<'a, T>(pub &'a T, pub Syntax);
@@ -5,6 +5,11 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Debug, Clone)]
 pub enum Error {
     InvalidCharacter(char),
+    UnterminatedString,
+    UnterminatedChar,
+    InvalidCharacterLiteral,
+    IntegerTooLarge(String),
+    NestingTooDeep,
     ExpectedButGot {
         expected: String,
         got: String,
@@ -27,6 +32,21 @@ impl Display for Error {
             Self::InvalidCharacter(c) => {
                 write!(formatter, "invalid character: '{c}'")
             }
+            Self::UnterminatedString => {
+                write!(formatter, "unterminated string literal")
+            }
+            Self::UnterminatedChar => {
+                write!(formatter, "unterminated character literal")
+            }
+            Self::InvalidCharacterLiteral => {
+                write!(formatter, "character literal must hold one ASCII character")
+            }
+            Self::IntegerTooLarge(literal) => {
+                write!(formatter, "integer literal is too large: {literal}")
+            }
+            Self::NestingTooDeep => {
+                write!(formatter, "nesting is too deep")
+            }
             Self::ExpectedButGot { expected, got } => {
                 write!(formatter, "expected {expected}, but got {got}")
             }
@@ -1,69 +1,116 @@
-use crate::*;
 use mellow_error::{Error, Result};
 
-pub struct Lex {
-    source: SourceBuffer,
+use crate::*;
+
+pub struct Lex<'a> {
+    source: SourceBuffer<'a>,
 }
 
-impl Lex {
-    pub fn new(source: SourceBuffer) -> Self {
+impl<'a> Lex<'a> {
+    pub fn new(source: SourceBuffer<'a>) -> Self {
         Self { source }
     }
 }
 
-impl Iterator for Lex {
-    type Item = Result<Token>;
+impl<'a> Iterator for Lex<'a> {
+    type Item = Result<Token<'a>>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.token()
     }
 }
 
-impl Lex {
-    fn token(&mut self) -> Option<Result<Token>> {
+impl<'a> Lex<'a> {
+    fn token(&mut self) -> Option<Result<Token<'a>>> {
         self.source.take_while(is_skip);
         let kind = match self.source.current()? {
             c if is_numeric(c) => self.numeric(),
-            c if is_alphabetic(c) => self.alphabetic(),
-            '"' => self.string(),
-            '=' => self.one(TokenKind::Equal),
-            '+' => self.one(TokenKind::Plus),
-            '-' => self.one(TokenKind::Minus),
-            '*' => self.one(TokenKind::Star),
-            '/' => self.one(TokenKind::Slash),
-            '>' => self.one(TokenKind::Greater),
-            '<' => self.one(TokenKind::Less),
-            '?' => self.one(TokenKind::Question),
-            '!' => self.one(TokenKind::Not),
-            '(' => self.one(TokenKind::LeftParenthesis),
-            ')' => self.one(TokenKind::RightParenthesis),
-            c => {
-                return Some(Err(Error::InvalidCharacter(c)));
+            c if is_alphabetic(c) => Ok(self.alphabetic()),
+            b'"' => self.string(),
+            b'\'' => self.char(),
+            b'=' => self.one(TokenKind::Equal),
+            b'+' => self.one_or_two(TokenKind::Plus, b'=', TokenKind::PlusEqual),
+            b'-' => self.one_or_two(TokenKind::Minus, b'=', TokenKind::MinusEqual),
+            b'*' => self.star(),
+            b'/' => self.one_or_two(TokenKind::Slash, b'=', TokenKind::SlashEqual),
+            b'%' => self.one_or_two(TokenKind::Percent, b'=', TokenKind::PercentEqual),
+            b'>' => self.one(TokenKind::Greater),
+            b'<' => self.one(TokenKind::Less),
+            b'?' => self.one(TokenKind::Question),
+            b'!' => self.one(TokenKind::Not),
+            b'(' => self.one(TokenKind::LeftParenthesis),
+            b')' => self.one(TokenKind::RightParenthesis),
+            b'[' => self.one(TokenKind::LeftBracket),
+            b']' => self.one(TokenKind::RightBracket),
+            b',' => self.one(TokenKind::Comma),
+            b'.' => self.one(TokenKind::Dot),
+            _ => {
+                let c = self.source.current_char()?;
+                self.source.advance_char();
+                Err(Error::InvalidCharacter(c))
             }
         };
-        
-        Some(Ok(Token::new(kind)))
+
+        Some(kind.map(Token::new))
     }
 
-    fn numeric(&mut self) -> TokenKind {
+    fn numeric(&mut self) -> Result<TokenKind<'a>> {
         let buffer = self.source.take_while(is_numeric);
         TokenKind::from_numeric(buffer)
     }
 
-    fn alphabetic(&mut self) -> TokenKind {
+    fn alphabetic(&mut self) -> TokenKind<'a> {
         let buffer = self.source.take_while(is_alphanumeric);
         TokenKind::from_alphabetic(buffer)
     }
 
-    fn string(&mut self) -> TokenKind {
+    fn string(&mut self) -> Result<TokenKind<'a>> {
+        self.source.eat();
+        let buffer = self.source.take_while(|c| c != b'"');
+        match self.source.eat() {
+            Some(_) => Ok(TokenKind::String(buffer)),
+            None => Err(Error::UnterminatedString),
+        }
+    }
+
+    fn char(&mut self) -> Result<TokenKind<'a>> {
+        self.source.eat();
+        let buffer = self.source.take_while(|c| c != b'\'');
+        if self.source.eat().is_none() {
+            return Err(Error::UnterminatedChar);
+        }
+        match buffer.as_bytes() {
+            [c] if c.is_ascii() => Ok(TokenKind::Char(*c)),
+            _ => Err(Error::InvalidCharacterLiteral),
+        }
+    }
+
+    fn one(&mut self, kind: TokenKind<'a>) -> Result<TokenKind<'a>> {
         self.source.eat();
-        let buffer = self.source.take_while(|c| c != '"');
+        Ok(kind)
+    }
+
+    fn star(&mut self) -> Result<TokenKind<'a>> {
         self.source.eat();
-        TokenKind::String(buffer)
+        if self.source.eat_if(b'*') {
+            return Ok(TokenKind::DoubleStar);
+        }
+        if self.source.eat_if(b'=') {
+            return Ok(TokenKind::StarEqual);
+        }
+        Ok(TokenKind::Star)
     }
 
-    fn one(&mut self, kind: TokenKind) -> TokenKind {
+    fn one_or_two(
+        &mut self,
+        one: TokenKind<'a>,
+        second: u8,
+        two: TokenKind<'a>,
+    ) -> Result<TokenKind<'a>> {
         self.source.eat();
-        kind
+        if self.source.eat_if(second) {
+            return Ok(two);
+        }
+        Ok(one)
     }
 }
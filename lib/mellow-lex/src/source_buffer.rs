@@ -1,66 +1,81 @@
-pub struct SourceBuffer {
-    buffer: Vec<char>,
+pub struct SourceBuffer<'a> {
+    buffer: &'a str,
     current: usize,
 }
 
-impl From<String> for SourceBuffer {
-    fn from(buffer: String) -> Self {
-        let buffer = buffer.chars().collect();
+impl<'a> From<&'a str> for SourceBuffer<'a> {
+    fn from(buffer: &'a str) -> Self {
         Self::new(buffer)
     }
 }
 
-impl From<&str> for SourceBuffer {
-    fn from(buffer: &str) -> Self {
-        let buffer = buffer.chars().collect();
+impl<'a> From<&'a String> for SourceBuffer<'a> {
+    fn from(buffer: &'a String) -> Self {
         Self::new(buffer)
     }
 }
 
-impl SourceBuffer {
-    pub fn new(buffer: Vec<char>) -> Self {
+impl<'a> SourceBuffer<'a> {
+    pub fn new(buffer: &'a str) -> Self {
         Self { buffer, current: 0 }
     }
 
-    pub fn eat(&mut self) -> char {
-        let c = self.current().unwrap();
+    pub fn eat(&mut self) -> Option<u8> {
+        let c = self.current()?;
         self.advance();
-        c
+        Some(c)
     }
 
-    pub fn take_while(&mut self, pattern: fn(char) -> bool) -> String {
-        let mut output = String::new();
+    pub fn take_while(&mut self, pattern: fn(u8) -> bool) -> &'a str {
+        let start = self.current;
         while self.current_is(pattern) {
-            output.push(self.eat());
+            self.advance();
         }
-        output
+        &self.buffer[start..self.current]
     }
 
-    pub fn current_is(&self, f: fn(char) -> bool) -> bool {
+    /// Consumes the current byte if it is `c`.
+    pub fn eat_if(&mut self, c: u8) -> bool {
+        if self.current() == Some(c) {
+            self.advance();
+            return true;
+        }
+        false
+    }
+
+    pub fn current_is(&self, f: fn(u8) -> bool) -> bool {
         self.current().is_some_and(f)
     }
 
-    pub fn current(&self) -> Option<char> {
-        self.buffer.get(self.current).cloned()
+    pub fn current(&self) -> Option<u8> {
+        self.buffer.as_bytes().get(self.current).copied()
+    }
+
+    pub fn current_char(&self) -> Option<char> {
+        self.buffer.get(self.current..)?.chars().next()
     }
 
     pub fn advance(&mut self) {
         self.current += 1;
     }
+
+    pub fn advance_char(&mut self) {
+        self.current += self.current_char().map_or(1, char::len_utf8);
+    }
 }
 
-pub fn is_alphanumeric(c: char) -> bool {
+pub fn is_alphanumeric(c: u8) -> bool {
     is_alphabetic(c) | is_numeric(c)
 }
 
-pub fn is_numeric(c: char) -> bool {
-    matches!(c, '0'..='9')
+pub fn is_numeric(c: u8) -> bool {
+    c.is_ascii_digit()
 }
 
-pub fn is_alphabetic(c: char) -> bool {
-    matches!(c, 'a'..='z' | 'A'..='Z' | '_')
+pub fn is_alphabetic(c: u8) -> bool {
+    c.is_ascii_alphabetic() || c == b'_'
 }
 
-pub fn is_skip(c: char) -> bool {
-    matches!(c, ' ' | '\t' | '\n')
+pub fn is_skip(c: u8) -> bool {
+    matches!(c, b' ' | b'\t' | b'\n')
 }
@@ -1,34 +1,41 @@
-#[derive(Clone, Debug)]
-pub struct Token {
-    kind: TokenKind,
+use std::fmt::{self, Display, Formatter};
+
+use mellow_error::{Error, Result};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Token<'a> {
+    kind: TokenKind<'a>,
     // todo: span tracking
 }
 
-impl Token {
-    pub fn new(kind: TokenKind) -> Self {
+impl<'a> Token<'a> {
+    pub fn new(kind: TokenKind<'a>) -> Self {
         Self { kind }
     }
 
-    pub fn kind(&self) -> &TokenKind {
+    pub fn kind(&self) -> &TokenKind<'a> {
         &self.kind
     }
 
-    pub fn take_kind(self) -> TokenKind {
+    pub fn take_kind(self) -> TokenKind<'a> {
         self.kind
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub enum TokenKind {
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum TokenKind<'a> {
     // literals
-    Integer(i128),
-    Identifier(String),
-    String(String),
+    /// Up to `2^63`, which is only valid negated.
+    Integer(u64),
+    Identifier(&'a str),
+    String(&'a str),
+    Char(u8),
     // keywords
     True,
     False,
     Let,
     Mutable,
+    Const,
     If,
     Or,
     Else,
@@ -42,28 +49,41 @@ pub enum TokenKind {
     Plus,
     Minus,
     Star,
+    DoubleStar,
     Slash,
+    Percent,
+    PlusEqual,
+    MinusEqual,
+    StarEqual,
+    SlashEqual,
+    PercentEqual,
     Greater,
     Less,
     Question,
     Not,
     LeftParenthesis,
     RightParenthesis,
+    LeftBracket,
+    RightBracket,
+    Comma,
+    Dot,
 }
 
-impl TokenKind {
-    pub fn from_numeric(buffer: String) -> Self {
-        let buffer = buffer.replace('_', "");
-        let value = buffer.parse().unwrap();
-        Self::Integer(value)
+impl<'a> TokenKind<'a> {
+    pub fn from_numeric(buffer: &'a str) -> Result<Self> {
+        match buffer.parse() {
+            Ok(value) if value <= i64::MIN.unsigned_abs() => Ok(Self::Integer(value)),
+            _ => Err(Error::IntegerTooLarge(buffer.into())),
+        }
     }
 
-    pub fn from_alphabetic(buffer: String) -> Self {
-        match buffer.as_str() {
+    pub fn from_alphabetic(buffer: &'a str) -> Self {
+        match buffer {
             "true" => Self::True,
             "false" => Self::False,
             "let" => Self::Let,
             "mutable" => Self::Mutable,
+            "const" => Self::Const,
             "if" => Self::If,
             "or" => Self::Or,
             "else" => Self::Else,
@@ -76,3 +96,50 @@ impl TokenKind {
         }
     }
 }
+
+impl Display for TokenKind<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let symbol = match self {
+            Self::Integer(value) => return write!(f, "integer {value}"),
+            Self::Identifier(name) => return write!(f, "identifier '{name}'"),
+            Self::String(value) => return write!(f, "string \"{value}\""),
+            Self::Char(value) => return write!(f, "character '{}'", *value as char),
+            Self::True => "true",
+            Self::False => "false",
+            Self::Let => "let",
+            Self::Mutable => "mutable",
+            Self::Const => "const",
+            Self::If => "if",
+            Self::Or => "or",
+            Self::Else => "else",
+            Self::Then => "then",
+            Self::While => "while",
+            Self::Do => "do",
+            Self::End => "end",
+            Self::Debug => "debug",
+            Self::Equal => "=",
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Star => "*",
+            Self::DoubleStar => "**",
+            Self::Slash => "/",
+            Self::Percent => "%",
+            Self::PlusEqual => "+=",
+            Self::MinusEqual => "-=",
+            Self::StarEqual => "*=",
+            Self::SlashEqual => "/=",
+            Self::PercentEqual => "%=",
+            Self::Greater => ">",
+            Self::Less => "<",
+            Self::Question => "?",
+            Self::Not => "!",
+            Self::LeftParenthesis => "(",
+            Self::RightParenthesis => ")",
+            Self::LeftBracket => "[",
+            Self::RightBracket => "]",
+            Self::Comma => ",",
+            Self::Dot => ".",
+        };
+        write!(f, "'{symbol}'")
+    }
+}
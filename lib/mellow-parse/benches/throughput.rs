@@ -0,0 +1,73 @@
+use std::{
+    fmt::Write,
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+use mellow_lex::{Lex, SourceBuffer};
+use mellow_parse::Parse;
+
+const TARGET_SIZE: usize = 8 * 1024 * 1024;
+const RUNS: u32 = 5;
+
+fn generate(size: usize) -> String {
+    let mut source = String::with_capacity(size + 256);
+    let mut index: usize = 0;
+    while source.len() < size {
+        let previous = index.saturating_sub(1);
+        writeln!(
+            source,
+            "let mutable value_{index} = {index} * (value_{previous} + 42) - 7"
+        )
+        .unwrap();
+        writeln!(source, "debug \"value_{index}\"").unwrap();
+        writeln!(source, "if value_{index} > 100 then").unwrap();
+        writeln!(source, "      value_{index} = value_{index} / 2").unwrap();
+        writeln!(source, "end").unwrap();
+        index += 1;
+    }
+    source
+}
+
+fn measure<F: FnMut() -> usize>(mut f: F) -> (Duration, usize) {
+    let mut best = Duration::MAX;
+    let mut count = 0;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        count = black_box(f());
+        best = best.min(start.elapsed());
+    }
+    (best, count)
+}
+
+fn report(name: &str, bytes: usize, (elapsed, count): (Duration, usize), unit: &str) {
+    let megabytes = bytes as f64 / (1024.0 * 1024.0);
+    println!(
+        "{name:<6} {count:>9} {unit:<10} {:>9.2} ms {:>9.2} MiB/s",
+        elapsed.as_secs_f64() * 1000.0,
+        megabytes / elapsed.as_secs_f64(),
+    );
+}
+
+fn main() {
+    let source = generate(TARGET_SIZE);
+    println!(
+        "source: {:.2} MiB, best of {RUNS} runs",
+        source.len() as f64 / (1024.0 * 1024.0)
+    );
+
+    let lex = measure(|| {
+        Lex::new(SourceBuffer::from(black_box(source.as_str())))
+            .try_fold(0, |count, token| token.map(|_| count + 1))
+            .unwrap()
+    });
+    report("lex", source.len(), lex, "tokens");
+
+    let parse = measure(|| {
+        let lex = Lex::new(SourceBuffer::from(black_box(source.as_str())));
+        Parse::new(lex.peekable())
+            .try_fold(0, |count, statement| statement.map(|_| count + 1))
+            .unwrap()
+    });
+    report("parse", source.len(), parse, "statements");
+}
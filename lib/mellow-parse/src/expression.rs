@@ -3,10 +3,7 @@ use mellow_lex::TokenKind;
 
 use super::*;
 
-use crate::{
-    Parsable, Parse, literal,
-    ExpressionState, Rpn, RpnItem,
-};
+use crate::{Associativity, Parsable, Parse, Precedence};
 
 #[derive(Debug, Clone)]
 pub enum Expression {
@@ -14,20 +11,32 @@ pub enum Expression {
     Identifier(Identifier),
     Boolean(Boolean),
     String(Str),
+    Char(Char),
     Binary(Binary),
     Unary(Unary),
     If(If<Expression>),
+    Call(Call),
+    Index(Index),
+    Field(Field),
 }
 
-impl From<TokenKind> for Expression {
-    fn from(kind: TokenKind) -> Self {
+impl TryFrom<TokenKind<'_>> for Expression {
+    type Error = Error;
+
+    fn try_from(kind: TokenKind) -> Result<Self> {
         match kind {
-            TokenKind::Integer(value) => Self::Integer(Integer { value }),
-            TokenKind::Identifier(name) => Self::Identifier(Identifier { name }),
-            TokenKind::True => Self::Boolean(Boolean { value: true }),
-            TokenKind::False => Self::Boolean(Boolean { value: false }),
-            TokenKind::String(value) => Self::String(Str { value }),
-            _ => panic!(),
+            TokenKind::Integer(value) => match i64::try_from(value) {
+                Ok(value) => Ok(Self::Integer(Integer { value })),
+                Err(_) => Err(Error::IntegerTooLarge(value.to_string())),
+            },
+            TokenKind::Identifier(name) => Ok(Self::Identifier(Identifier::from(name))),
+            TokenKind::True => Ok(Self::Boolean(Boolean { value: true })),
+            TokenKind::False => Ok(Self::Boolean(Boolean { value: false })),
+            TokenKind::String(value) => Ok(Self::String(Str {
+                value: value.to_owned(),
+            })),
+            TokenKind::Char(value) => Ok(Self::Char(Char { value })),
+            _ => Err(Error::expected_but_got("literal", kind)),
         }
     }
 }
@@ -37,55 +46,129 @@ impl Parsable for Expression {
     where
         Self: Sized,
     {
-        let mut rpn = Rpn::new();
-        let mut status = ExpressionState::default();
+        Self::climb(source, 0)
+    }
+}
+
+impl Expression {
+    /// Parses an expression whose infix operators bind at least as tight as
+    /// `minimum`.
+    fn climb(source: &mut Parse, minimum: u8) -> Result<Self> {
+        source.nest(|source| Self::climb_nested(source, minimum))
+    }
+
+    /// Also records the height of the expression, as operators folded into
+    /// `left` make it taller than the nesting.
+    fn climb_nested(source: &mut Parse, minimum: u8) -> Result<Self> {
+        let outer = source.take_height();
+        let mut left = Self::prefix(source)?;
+        let mut height = source.take_height() + 1;
         while let Some(token) = source.peek()? {
-            let kind = token.take_kind();
-            if status.stop(&kind)? {
+            let kind = *token.kind();
+            if matches!(
+                kind,
+                TokenKind::LeftParenthesis | TokenKind::LeftBracket | TokenKind::Dot
+            ) {
+                left = Self::postfix(source, left)?;
+                height = height.max(source.take_height()) + 1;
+                source.record_height(height)?;
+                continue;
+            }
+            let Ok(binary) = BinaryKind::try_from(&kind) else {
+                break;
+            };
+            if binary.precedence() < minimum {
                 break;
             }
-            match kind {
-                literal!() => {
-                    rpn.value(Expression::from(kind));
-                    source.next()?;
-                }
-                ref token if BinaryKind::try_from(token).is_ok() => {
-                    // TODO: if let guard
-                    let binary = BinaryKind::try_from(token).unwrap();
-                    rpn.binary(binary);
-                    source.next()?;
-                }
-                ref token if UnaryKind::try_from(token).is_ok() => {
-                    // TODO: if let guard
-                    let unary = UnaryKind::try_from(token).unwrap();
-                    rpn.unary(unary);
-                    source.next()?;
-                }
-                TokenKind::LeftParenthesis => {
-                    rpn.item(RpnItem::Parenthesis);
-                    source.next()?;
-                }
-                TokenKind::RightParenthesis => {
-                    source.next()?;
-                    rpn.parenthesis();
-                }
-                TokenKind::If => {
-                    source.next()?;
-                    let expression = If::<Expression>::parse(source)?;
-                    rpn.value(Expression::If(expression));
+            source.advance()?;
+            let next = match binary.associativity() {
+                Associativity::Left => binary.precedence() + 1,
+                Associativity::Right => binary.precedence(),
+            };
+            let right = Self::climb(source, next)?;
+            left = Self::Binary(Binary::new(binary, left, right));
+            height = height.max(source.take_height()) + 1;
+            source.record_height(height)?;
+        }
+        source.record_height(outer.max(height))?;
+        Ok(left)
+    }
+
+    fn prefix(source: &mut Parse) -> Result<Self> {
+        let Some(token) = source.peek()? else {
+            return Err(Error::expected_but_got("expression", "EOF"));
+        };
+        let kind = *token.kind();
+        match kind {
+            TokenKind::Integer(_)
+            | TokenKind::Identifier(_)
+            | TokenKind::True
+            | TokenKind::False
+            | TokenKind::String(_)
+            | TokenKind::Char(_) => {
+                source.advance()?;
+                Self::try_from(kind)
+            }
+            TokenKind::LeftParenthesis => {
+                source.advance()?;
+                let inner = Self::climb(source, 0)?;
+                source.expect(TokenKind::RightParenthesis)?;
+                Ok(inner)
+            }
+            TokenKind::If => Ok(Self::If(If::<Expression>::parse(source)?)),
+            ref kind => match UnaryKind::try_from(kind) {
+                Ok(unary) => {
+                    source.advance()?;
+                    // `i64::MIN` has no positive literal to negate
+                    if unary == UnaryKind::Negate
+                        && let Some(token) = source.peek()?
+                        && *token.kind() == TokenKind::Integer(i64::MIN.unsigned_abs())
+                    {
+                        source.advance()?;
+                        return Ok(Self::Integer(Integer { value: i64::MIN }));
+                    }
+                    let inner = Self::climb(source, unary.precedence())?;
+                    Ok(Self::Unary(Unary::new(unary, inner)))
                 }
-                _ => {
-                    return Err(Error::expected_but_got("expression", "todo"));
+                Err(_) => Err(Error::expected_but_got("expression", kind)),
+            },
+        }
+    }
+
+    fn postfix(source: &mut Parse, target: Self) -> Result<Self> {
+        match source.advance()?.take_kind() {
+            TokenKind::LeftParenthesis => {
+                let mut arguments = Vec::new();
+                if !source.eat(TokenKind::RightParenthesis)? {
+                    loop {
+                        arguments.push(Self::climb(source, 0)?);
+                        if source.eat(TokenKind::RightParenthesis)? {
+                            break;
+                        }
+                        if !source.eat(TokenKind::Comma)? {
+                            return Err(source.unexpected("',' or ')' in argument list"));
+                        }
+                    }
                 }
+                Ok(Self::Call(Call::new(target, arguments)))
+            }
+            TokenKind::LeftBracket => {
+                let index = Self::climb(source, 0)?;
+                source.expect(TokenKind::RightBracket)?;
+                Ok(Self::Index(Index::new(target, index)))
             }
+            TokenKind::Dot => {
+                let field = Identifier::parse(source)?;
+                Ok(Self::Field(Field::new(target, field)))
+            }
+            kind => Err(Error::expected_but_got("postfix operator", kind)),
         }
-        Ok(rpn.collapse())
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Integer {
-    pub value: i128,
+    pub value: i64,
 }
 
 #[derive(Debug, Clone)]
@@ -98,6 +181,11 @@ pub struct Str {
     pub value: String,
 }
 
+#[derive(Debug, Clone)]
+pub struct Char {
+    pub value: u8,
+}
+
 #[derive(Debug, Clone)]
 pub struct Binary {
     pub kind: BinaryKind,
@@ -115,30 +203,34 @@ impl Binary {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum BinaryKind {
     Add,
     Subtract,
     Multiply,
     Divide,
+    Remainder,
+    Power,
     Greater,
     Less,
     Equal,
 }
 
-impl TryFrom<&TokenKind> for BinaryKind {
-    type Error = ();
+impl TryFrom<&TokenKind<'_>> for BinaryKind {
+    type Error = Error;
 
-    fn try_from(kind: &TokenKind) -> std::result::Result<Self, Self::Error> {
+    fn try_from(kind: &TokenKind) -> Result<Self> {
         match kind {
             TokenKind::Plus => Ok(BinaryKind::Add),
             TokenKind::Minus => Ok(BinaryKind::Subtract),
             TokenKind::Star => Ok(BinaryKind::Multiply),
             TokenKind::Slash => Ok(BinaryKind::Divide),
+            TokenKind::Percent => Ok(BinaryKind::Remainder),
+            TokenKind::DoubleStar => Ok(BinaryKind::Power),
             TokenKind::Greater => Ok(BinaryKind::Greater),
             TokenKind::Less => Ok(BinaryKind::Less),
             TokenKind::Question => Ok(BinaryKind::Equal),
-            _ => todo!("remove this try_from implementation"),
+            _ => Err(Error::expected_but_got("binary operator", kind)),
         }
     }
 }
@@ -164,13 +256,59 @@ pub enum UnaryKind {
     Negate,
 }
 
-impl TryFrom<&TokenKind> for UnaryKind {
-    type Error = ();
+impl TryFrom<&TokenKind<'_>> for UnaryKind {
+    type Error = Error;
 
-    fn try_from(kind: &TokenKind) -> std::result::Result<Self, Self::Error> {
+    fn try_from(kind: &TokenKind) -> Result<Self> {
         match kind {
             TokenKind::Not => Ok(UnaryKind::Not),
-            _ => todo!("remove this try_from implementation"),
+            TokenKind::Minus => Ok(UnaryKind::Negate),
+            _ => Err(Error::expected_but_got("unary operator", kind)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub callee: Box<Expression>,
+    pub arguments: Vec<Expression>,
+}
+
+impl Call {
+    pub fn new(callee: Expression, arguments: Vec<Expression>) -> Self {
+        Self {
+            callee: Box::new(callee),
+            arguments,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Index {
+    pub target: Box<Expression>,
+    pub index: Box<Expression>,
+}
+
+impl Index {
+    pub fn new(target: Expression, index: Expression) -> Self {
+        Self {
+            target: Box::new(target),
+            index: Box::new(index),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Field {
+    pub target: Box<Expression>,
+    pub field: Identifier,
+}
+
+impl Field {
+    pub fn new(target: Expression, field: Identifier) -> Self {
+        Self {
+            target: Box::new(target),
+            field,
         }
     }
 }
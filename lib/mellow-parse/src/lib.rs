@@ -1,5 +1,3 @@
-mod rpn;
-pub use rpn::*;
 mod parse;
 pub use parse::*;
 mod precedence;
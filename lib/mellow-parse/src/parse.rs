@@ -5,19 +5,32 @@ use mellow_lex::{Lex, Token, TokenKind};
 
 use super::Statement;
 
-pub type Source = Peekable<Lex>;
+pub type Source<'a> = Peekable<Lex<'a>>;
 
-pub struct Parse {
-    source: Source,
+/// How deep expressions and bodies may nest before parsing gives up, so that
+/// hostile input cannot overflow the stack.
+const MAX_DEPTH: usize = 256;
+/// How tall an expression may be. Operators folded into a left operand make
+/// it taller without nesting, and later passes recurse over it.
+const MAX_HEIGHT: usize = 4096;
+
+pub struct Parse<'a> {
+    source: Source<'a>,
+    depth: usize,
+    height: usize,
 }
 
-impl Parse {
-    pub fn new(source: Source) -> Self {
-        Self { source }
+impl<'a> Parse<'a> {
+    pub fn new(source: Source<'a>) -> Self {
+        Self {
+            source,
+            depth: 0,
+            height: 0,
+        }
     }
 }
 
-impl Iterator for Parse {
+impl Iterator for Parse<'_> {
     type Item = Result<Statement>;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -26,34 +39,77 @@ impl Iterator for Parse {
     }
 }
 
-impl Parse {
-    pub fn next(&mut self) -> Result<Token> {
+impl<'a> Parse<'a> {
+    pub fn advance(&mut self) -> Result<Token<'a>> {
         self.source
             .next()
             .unwrap_or(Err(Error::expected_but_got("statement", "EOF")))
     }
 
-    pub fn peek(&mut self) -> Result<Option<Token>> {
-        self.source.peek().cloned().transpose()
+    pub fn peek(&mut self) -> Result<Option<&Token<'a>>> {
+        match self.source.peek() {
+            Some(Ok(token)) => Ok(Some(token)),
+            Some(Err(error)) => Err(error.clone()),
+            None => Ok(None),
+        }
     }
 
     pub fn expect(&mut self, token: TokenKind) -> Result<()> {
-        let next = self.next()?.take_kind();
-        if next == token {
+        if self.eat(token)? {
             Ok(())
         } else {
-            Err(Error::expected_but_got("todo", "todo"))
+            Err(self.unexpected(token))
         }
     }
 
-    pub fn mutable(&mut self) -> Result<bool> {
-        match self.peek()?.and_then(|token| Some(token.take_kind())) {
-            Some(TokenKind::Mutable) => {
-                self.next()?;
-                Ok(true)
-            }
-            _ => Ok(false),
+    /// Consumes the next token if it is `token`.
+    pub fn eat(&mut self, token: TokenKind) -> Result<bool> {
+        if self.peek()?.is_some_and(|next| next.kind() == &token) {
+            self.advance()?;
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    /// Builds an error describing what was expected in place of the next
+    /// token.
+    pub fn unexpected<E: ToString>(&mut self, expected: E) -> Error {
+        match self.peek() {
+            Ok(Some(token)) => Error::expected_but_got(expected, token.kind()),
+            Ok(None) => Error::expected_but_got(expected, "EOF"),
+            Err(error) => error,
+        }
+    }
+
+    /// Runs `f` one nesting level deeper.
+    pub fn nest<T>(&mut self, f: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if self.depth == MAX_DEPTH {
+            return Err(Error::NestingTooDeep);
         }
+        self.depth += 1;
+        let output = f(self);
+        self.depth -= 1;
+        output
+    }
+
+    /// Takes the height of the tallest expression recorded since the last
+    /// call, or 0 if there was none.
+    pub fn take_height(&mut self) -> usize {
+        std::mem::take(&mut self.height)
+    }
+
+    /// Records an expression of `height` levels, which must not be too tall
+    /// for the passes that recurse over it.
+    pub fn record_height(&mut self, height: usize) -> Result<()> {
+        if height > MAX_HEIGHT {
+            return Err(Error::NestingTooDeep);
+        }
+        self.height = self.height.max(height);
+        Ok(())
+    }
+
+    pub fn mutable(&mut self) -> Result<bool> {
+        self.eat(TokenKind::Mutable)
     }
 }
 
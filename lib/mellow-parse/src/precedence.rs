@@ -1,7 +1,17 @@
-use crate::{BinaryKind, RpnItem, UnaryKind};
+use crate::{BinaryKind, UnaryKind};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Associativity {
+    Left,
+    Right,
+}
 
 pub trait Precedence {
     fn precedence(&self) -> u8;
+
+    fn associativity(&self) -> Associativity {
+        Associativity::Left
+    }
 }
 
 impl Precedence for BinaryKind {
@@ -11,28 +21,28 @@ impl Precedence for BinaryKind {
             Self::Subtract => 2,
             Self::Multiply => 3,
             Self::Divide => 3,
+            Self::Remainder => 3,
+            Self::Power => 5,
             Self::Greater => 1,
             Self::Less => 1,
             Self::Equal => 1,
         }
     }
-}
 
-impl Precedence for UnaryKind {
-    fn precedence(&self) -> u8 {
+    fn associativity(&self) -> Associativity {
         match self {
-            Self::Negate => 4,
-            Self::Not => 4,
+            Self::Power => Associativity::Right,
+            _ => Associativity::Left,
         }
     }
 }
 
-impl Precedence for RpnItem {
+// binds looser than `**`, so `-a ** b` is `-(a ** b)`
+impl Precedence for UnaryKind {
     fn precedence(&self) -> u8 {
         match self {
-            Self::Binary(kind) => kind.precedence(),
-            Self::Unary(kind) => kind.precedence(),
-            Self::Parenthesis => 0,
+            Self::Negate => 4,
+            Self::Not => 4,
         }
     }
 }
@@ -1,13 +1,19 @@
-use std::vec::IntoIter;
+use std::{
+    fmt::{self, Display},
+    slice::Iter,
+    vec::IntoIter,
+};
 
-use mellow_error::{Error, Result};
-use mellow_lex::TokenKind;
+use mellow_error::Result;
+use mellow_lex::{Token, TokenKind};
+use mellow_symbol::Symbol;
 
-use crate::{Expression, Parsable, Parse};
+use crate::{BinaryKind, Expression, Parsable, Parse};
 
 #[derive(Debug, Clone)]
 pub enum Statement {
     Let(Let),
+    Const(Const),
     Assign(Assign),
     If(If<Body>),
     While(While),
@@ -19,14 +25,14 @@ impl Parsable for Statement {
     where
         Self: Sized,
     {
-        match source.peek()?.and_then(|token| Some(token.take_kind())) {
+        match source.peek()?.map(Token::kind) {
             Some(TokenKind::Let) => Ok(Self::Let(Let::parse(source)?)),
+            Some(TokenKind::Const) => Ok(Self::Const(Const::parse(source)?)),
             Some(TokenKind::Identifier(..)) => Ok(Self::Assign(Assign::parse(source)?)),
             Some(TokenKind::If) => Ok(Self::If(If::<Body>::parse(source)?)),
             Some(TokenKind::While) => Ok(Self::While(While::parse(source)?)),
             Some(TokenKind::Debug) => Ok(Self::Debug(Debug::parse(source)?)),
-            Some(_) => Err(Error::expected_but_got("statement", "todo")),
-            _ => Err(Error::expected_but_got("statement", "EOF")),
+            _ => Err(source.unexpected("statement")),
         }
     }
 }
@@ -62,38 +68,85 @@ impl Parsable for Let {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// `const name = value`, where `value` is evaluated at compile time.
+#[derive(Debug, Clone)]
+pub struct Const {
+    pub identifier: Identifier,
+    pub value: Expression,
+}
+
+impl Const {
+    pub fn new(identifier: Identifier, value: Expression) -> Self {
+        Self { identifier, value }
+    }
+}
+
+impl Parsable for Const {
+    fn parse(source: &mut crate::Parse) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        source.expect(TokenKind::Const)?;
+        let identifier = Identifier::parse(source)?;
+        source.expect(TokenKind::Equal)?;
+        let value = Expression::parse(source)?;
+        Ok(Self::new(identifier, value))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Identifier {
-    pub name: String,
+    pub name: Symbol,
 }
 
 impl Identifier {
-    pub fn new(name: String) -> Self {
+    pub fn new(name: Symbol) -> Self {
         Self { name }
     }
 }
 
+impl From<&str> for Identifier {
+    fn from(name: &str) -> Self {
+        Self::new(Symbol::intern(name))
+    }
+}
+
+impl Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name)
+    }
+}
+
 impl Parsable for Identifier {
     fn parse(source: &mut crate::Parse) -> Result<Self>
     where
         Self: Sized,
     {
-        match source.next()?.take_kind() {
-            TokenKind::Identifier(name) => Ok(Identifier::new(name)),
-            _ => Err(Error::expected_but_got("identifier", "todo")),
+        match source.peek()?.map(Token::kind) {
+            Some(&TokenKind::Identifier(name)) => {
+                source.advance()?;
+                Ok(Identifier::from(name))
+            }
+            _ => Err(source.unexpected("identifier")),
         }
     }
 }
 
+/// `name = value`, or `name += value` and friends when `operator` is set.
 #[derive(Debug, Clone)]
 pub struct Assign {
     pub identifier: Identifier,
+    pub operator: Option<BinaryKind>,
     pub value: Expression,
 }
 
 impl Assign {
-    pub fn new(identifier: Identifier, value: Expression) -> Self {
-        Self { identifier, value }
+    pub fn new(identifier: Identifier, operator: Option<BinaryKind>, value: Expression) -> Self {
+        Self {
+            identifier,
+            operator,
+            value,
+        }
     }
 }
 
@@ -103,9 +156,18 @@ impl Parsable for Assign {
         Self: Sized,
     {
         let identifier = Identifier::parse(source)?;
-        source.expect(TokenKind::Equal)?;
+        let operator = match source.peek()?.map(Token::kind) {
+            Some(TokenKind::Equal) => None,
+            Some(TokenKind::PlusEqual) => Some(BinaryKind::Add),
+            Some(TokenKind::MinusEqual) => Some(BinaryKind::Subtract),
+            Some(TokenKind::StarEqual) => Some(BinaryKind::Multiply),
+            Some(TokenKind::SlashEqual) => Some(BinaryKind::Divide),
+            Some(TokenKind::PercentEqual) => Some(BinaryKind::Remainder),
+            _ => return Err(source.unexpected("'=' or compound assignment")),
+        };
+        source.advance()?;
         let value = Expression::parse(source)?;
-        Ok(Assign::new(identifier, value))
+        Ok(Assign::new(identifier, operator, value))
     }
 }
 
@@ -134,36 +196,24 @@ where
 
     fn or(source: &mut Parse) -> Result<Vec<Branch<B>>> {
         let mut or = Vec::new();
-        while source
-            .peek()?
-            .is_some_and(|token| token.kind() == &TokenKind::Or)
-        {
-            source.next()?;
+        while source.eat(TokenKind::Or)? {
             let branch = Branch::<B>::parse(source)?;
             or.push(branch);
         }
-        match source.peek()?.and_then(|token| Some(token.take_kind())) {
+        match source.peek()?.map(Token::kind) {
             Some(TokenKind::Else) | Some(TokenKind::End) => Ok(or),
-            Some(_) => Err(Error::expected_but_got(
-                "'else' or 'end' after 'or' body",
-                "todo",
-            )),
-            _ => Err(Error::expected_but_got(
-                "'else' or 'end' after 'or' body",
-                "EOF",
-            )),
+            _ => Err(source.unexpected("'else' or 'end' after 'or' body")),
         }
     }
 
     fn else_(source: &mut Parse) -> Result<Option<Box<B>>> {
-        match source.peek()?.and_then(|token| Some(token.take_kind())) {
+        match source.peek()?.map(Token::kind) {
             Some(TokenKind::Else) => {
-                source.next()?;
+                source.advance()?;
                 Ok(Some(Box::new(B::parse(source)?)))
             }
             Some(TokenKind::End) => Ok(None),
-            Some(_) => Err(Error::expected_but_got("'else', 'or' or 'end'", "todo")),
-            _ => Err(Error::expected_but_got("'else', 'or' or 'end'", "EOF")),
+            _ => Err(source.unexpected("'else', 'or' or 'end'")),
         }
     }
 }
@@ -258,9 +308,15 @@ impl Parsable for Body {
     where
         Self: Sized,
     {
+        source.nest(Self::parse_nested)
+    }
+}
+
+impl Body {
+    fn parse_nested(source: &mut Parse) -> Result<Self> {
         let mut inner = Vec::new();
         while let Some(token) = source.peek()? {
-            match token.take_kind() {
+            match token.kind() {
                 TokenKind::Or | TokenKind::Else | TokenKind::End => break,
                 _ => {
                     let statement = Statement::parse(source)?;
@@ -281,14 +337,23 @@ impl IntoIterator for Body {
     }
 }
 
+impl<'a> IntoIterator for &'a Body {
+    type Item = &'a Statement;
+    type IntoIter = Iter<'a, Statement>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.inner.iter()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Debug {
-    pub value: Expression,
+    pub values: Vec<Expression>,
 }
 
 impl Debug {
-    pub fn new(value: Expression) -> Self {
-        Self { value }
+    pub fn new(values: Vec<Expression>) -> Self {
+        Self { values }
     }
 }
 
@@ -298,7 +363,10 @@ impl Parsable for Debug {
         Self: Sized,
     {
         source.expect(TokenKind::Debug)?;
-        let value = Expression::parse(source)?;
-        Ok(Self::new(value))
+        let mut values = vec![Expression::parse(source)?];
+        while source.eat(TokenKind::Comma)? {
+            values.push(Expression::parse(source)?);
+        }
+        Ok(Self::new(values))
     }
 }
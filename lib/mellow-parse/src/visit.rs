@@ -1,6 +1,6 @@
 use crate::{
-    Assign, Binary, Body, Boolean, Debug, Expression, Identifier, If, Integer, Let, Statement, Str,
-    Unary, While,
+    Assign, Binary, Body, Boolean, Call, Char, Const, Debug, Expression, Field, Identifier, If, Index,
+    Integer, Let, Statement, Str, Unary, While,
 };
 
 #[allow(unused)]
@@ -8,19 +8,22 @@ pub trait VisitStatement {
     type Output;
     type Context;
 
-    fn let_(&mut self, node: Let, context: &mut Self::Context) -> Self::Output {
+    fn let_(&mut self, node: &Let, context: &mut Self::Context) -> Self::Output {
         todo!()
     }
-    fn assign(&mut self, node: Assign, context: &mut Self::Context) -> Self::Output {
+    fn const_(&mut self, node: &Const, context: &mut Self::Context) -> Self::Output {
         todo!()
     }
-    fn if_(&mut self, node: If<Body>, context: &mut Self::Context) -> Self::Output {
+    fn assign(&mut self, node: &Assign, context: &mut Self::Context) -> Self::Output {
         todo!()
     }
-    fn while_(&mut self, node: While, context: &mut Self::Context) -> Self::Output {
+    fn if_(&mut self, node: &If<Body>, context: &mut Self::Context) -> Self::Output {
         todo!()
     }
-    fn debug(&mut self, node: Debug, context: &mut Self::Context) -> Self::Output {
+    fn while_(&mut self, node: &While, context: &mut Self::Context) -> Self::Output {
+        todo!()
+    }
+    fn debug(&mut self, node: &Debug, context: &mut Self::Context) -> Self::Output {
         todo!()
     }
 }
@@ -29,33 +32,46 @@ pub trait VisitStatement {
 pub trait VisitExpression {
     type Output;
 
-    fn integer(&mut self, node: Integer) -> Self::Output {
+    fn integer(&mut self, node: &Integer) -> Self::Output {
+        todo!()
+    }
+    fn identifier(&mut self, node: &Identifier) -> Self::Output {
+        todo!()
+    }
+    fn boolean(&mut self, node: &Boolean) -> Self::Output {
+        todo!()
+    }
+    fn string(&mut self, string: &Str) -> Self::Output {
+        todo!()
+    }
+    fn char(&mut self, node: &Char) -> Self::Output {
         todo!()
     }
-    fn identifier(&mut self, node: Identifier) -> Self::Output {
+    fn binary(&mut self, node: &Binary) -> Self::Output {
         todo!()
     }
-    fn boolean(&mut self, node: Boolean) -> Self::Output {
+    fn unary(&mut self, node: &Unary) -> Self::Output {
         todo!()
     }
-    fn string(&mut self, string: Str) -> Self::Output {
+    fn if_(&mut self, node: &If<Expression>) -> Self::Output {
         todo!()
     }
-    fn binary(&mut self, node: Binary) -> Self::Output {
+    fn call(&mut self, node: &Call) -> Self::Output {
         todo!()
     }
-    fn unary(&mut self, node: Unary) -> Self::Output {
+    fn index(&mut self, node: &Index) -> Self::Output {
         todo!()
     }
-    fn if_(&mut self, node: If<Expression>) -> Self::Output {
+    fn field(&mut self, node: &Field) -> Self::Output {
         todo!()
     }
 }
 
 impl Statement {
-    pub fn visit<T: VisitStatement>(self, visit: &mut T, context: &mut T::Context) -> T::Output {
+    pub fn visit<T: VisitStatement>(&self, visit: &mut T, context: &mut T::Context) -> T::Output {
         match self {
             Self::Let(node) => visit.let_(node, context),
+            Self::Const(node) => visit.const_(node, context),
             Self::Assign(node) => visit.assign(node, context),
             Self::If(node) => visit.if_(node, context),
             Self::While(node) => visit.while_(node, context),
@@ -65,15 +81,19 @@ impl Statement {
 }
 
 impl Expression {
-    pub fn visit<T: VisitExpression>(self, visit: &mut T) -> T::Output {
+    pub fn visit<T: VisitExpression>(&self, visit: &mut T) -> T::Output {
         match self {
             Self::Integer(node) => visit.integer(node),
             Self::Identifier(node) => visit.identifier(node),
             Self::Boolean(node) => visit.boolean(node),
             Self::String(node) => visit.string(node),
+            Self::Char(node) => visit.char(node),
             Self::Binary(node) => visit.binary(node),
             Self::Unary(node) => visit.unary(node),
             Self::If(node) => visit.if_(node),
+            Self::Call(node) => visit.call(node),
+            Self::Index(node) => visit.index(node),
+            Self::Field(node) => visit.field(node),
         }
     }
 }
@@ -0,0 +1,46 @@
+use mellow_error::{Error, Result};
+use mellow_lex::{Lex, SourceBuffer};
+use mellow_parse::{Parse, Statement};
+
+fn parse(source: &str) -> Result<Vec<Statement>> {
+    let lex = Lex::new(SourceBuffer::from(source));
+    Parse::new(lex.peekable()).collect()
+}
+
+fn too_deep(source: &str) -> bool {
+    matches!(parse(source), Err(Error::NestingTooDeep))
+}
+
+#[test]
+fn long_left_associative_chain() {
+    let terms = vec!["1"; 200_000];
+    assert!(too_deep(&format!("debug {}", terms.join(" + "))));
+}
+
+#[test]
+fn long_postfix_chain() {
+    assert!(too_deep(&format!("debug f{}", "()".repeat(200_000))));
+    assert!(too_deep(&format!("debug a{}", ".b".repeat(200_000))));
+}
+
+#[test]
+fn deep_parentheses() {
+    let source = format!("debug {}1{}", "(".repeat(200_000), ")".repeat(200_000));
+    assert!(too_deep(&source));
+}
+
+#[test]
+fn chains_longer_than_the_nesting_limit() {
+    let terms = vec!["1"; 300];
+    assert!(parse(&format!("debug {}", terms.join(" - "))).is_ok());
+    let terms = vec!["x"; 300];
+    assert!(parse(&format!("x = {}", terms.join(" + "))).is_ok());
+    assert!(parse(&format!("debug f{}", "()".repeat(300))).is_ok());
+}
+
+#[test]
+fn nested_chains_add_up() {
+    let chain = vec!["1"; 3000].join(" + ");
+    assert!(parse(&format!("debug {chain}")).is_ok());
+    assert!(too_deep(&format!("debug ({chain}) + {chain}")));
+}
@@ -0,0 +1,68 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Display, Formatter},
+    sync::{LazyLock, Mutex},
+};
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(|| Mutex::new(Interner::new()));
+
+/// Interned name. Comparing and hashing it is as cheap as for an integer, the
+/// text is only looked up when the symbol is printed.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    pub fn intern(text: &str) -> Self {
+        INTERNER.lock().unwrap().intern(text)
+    }
+
+    pub fn as_str(self) -> &'static str {
+        INTERNER.lock().unwrap().resolve(self)
+    }
+
+    pub fn id(self) -> u32 {
+        self.0
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    names: Vec<&'static str>,
+}
+
+impl Interner {
+    fn new() -> Self {
+        Self {
+            symbols: HashMap::new(),
+            names: Vec::new(),
+        }
+    }
+
+    fn intern(&mut self, text: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(text) {
+            return *symbol;
+        }
+        // names live until the compiler exits, so leaking them is fine
+        let name: &'static str = Box::leak(text.into());
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name);
+        self.symbols.insert(name, symbol);
+        symbol
+    }
+
+    fn resolve(&self, symbol: Symbol) -> &'static str {
+        self.names[symbol.0 as usize]
+    }
+}
@@ -26,14 +26,13 @@ extern void write_i64(long long value) {
       char buffer[20];
       int size = 0;
       int negative = value < 0;
+      // the magnitude of the smallest value does not fit in a signed integer
+      unsigned long long magnitude = negative ? -(unsigned long long)value : value;
 
-      if (negative)
-            value = -value;
-
-      while (value > 0) {
-            int digit = value % 10;
+      while (magnitude > 0) {
+            int digit = magnitude % 10;
             buffer[size++] = digit + '0';
-            value = (value - digit) / 10;
+            magnitude /= 10;
       }
 
       if (negative)
@@ -57,3 +56,12 @@ extern void debug_s(char *value) {
       write_s(value);
       write_c('\n');
 }
+
+extern void write_bool(long long value) {
+      write_s(value ? "true" : "false");
+}
+
+extern void debug_bool(long long value) {
+      write_bool(value);
+      write_c('\n');
+}
@@ -7,5 +7,7 @@ void write_i64(long long value);
 void debug_i64(long long value);
 void write_s(char *value);
 void debug_s(char *value);
+void write_bool(long long value);
+void debug_bool(long long value);
 
 #endif
@@ -0,0 +1,22 @@
+#include "math.h"
+
+extern long long power_i64(long long base, long long exponent) {
+      if (exponent < 0) {
+            if (base == 1)
+                  return 1;
+            if (base == -1)
+                  return exponent % 2 == 0 ? 1 : -1;
+            return 0;
+      }
+
+      long long result = 1;
+
+      while (exponent > 0) {
+            if (exponent & 1)
+                  result *= base;
+            base *= base;
+            exponent >>= 1;
+      }
+
+      return result;
+}
@@ -0,0 +1,6 @@
+#ifndef MATH_H
+#define MATH_H
+
+long long power_i64(long long base, long long exponent);
+
+#endif
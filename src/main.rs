@@ -1,18 +1,29 @@
-use std::{env, fs, process::exit};
+use std::{env, fs, io, os::unix::fs::PermissionsExt, process::exit};
 
+use mellow_assembly::{Allocator, Syntax};
 use mellow_lex::{Lex, SourceBuffer};
 use mellow_parse::Parse;
 
 fn main() {
-    let path = env::args().nth(1).unwrap_or("source.mellow".into());
-    let source = fs::read_to_string(path).unwrap();
     let args: Vec<_> = env::args().skip(1).collect();
+    // `mellow build` links a runnable executable
+    let build = args.first().is_some_and(|command| command == "build");
+    let path = args
+        .get(build as usize)
+        .filter(|path| !path.starts_with('-'))
+        .map_or("source.mellow", String::as_str);
+    let source = fs::read_to_string(path).unwrap();
+    let value = |flag: &str| {
+        args.iter()
+            .position(|argument| argument == flag)
+            .and_then(|index| args.get(index + 1))
+    };
 
-    let source_buffer = SourceBuffer::from(source);
+    let source_buffer = SourceBuffer::from(&source);
     let lex = Lex::new(source_buffer);
     let parse = Parse::new(lex.peekable());
 
-    let ast = match parse.collect() {
+    let ast = match parse.collect::<Result<Vec<_>, _>>() {
         Ok(ast) => ast,
         Err(error) => {
             eprintln!("error: {error}");
@@ -36,38 +47,93 @@ fn main() {
         println!("{symbol_table:#?}");
     }
 
-    let cfg = ir::cfg::construct(ast);
+    let cfg = ir::tac::construct(&ast, &symbol_table);
     if args.iter().any(|flag| flag == "--cfg") {
         println!("{cfg:#?}");
     }
 
-    let tac = ir::tac::construct(cfg);
+    let cfg = ir::tac::fold(cfg, &symbol_table);
+    let cfg = ir::tac::number(cfg);
+    let (cfg, unreachable) = ir::tac::eliminate(cfg);
+    for origin in unreachable {
+        eprintln!("warning: {origin} is unreachable");
+    }
+
+    let ssa = ir::ssa::construct(cfg);
+    if args.iter().any(|flag| flag == "--ssa") {
+        println!("{ssa:#?}");
+    }
+    let cfg = match ir::ssa::destruct(ssa) {
+        Ok(cfg) => cfg,
+        Err(error) => {
+            eprintln!("error: {error}");
+            exit(1);
+        }
+    };
+    let (cfg, _) = ir::tac::eliminate(cfg);
     if args.iter().any(|flag| flag == "--tac") {
-        println!("{tac:#?}");
+        println!("{:#?}", cfg.clone().linearize());
     }
 
-    println!("section .bss");
-    for (identifier, _) in symbol_table.variables() {
-        println!("{}: resq 1", identifier.name);
+    // colouring allocates better, and `-O2` asks for it by name, but linear
+    // scan keeps big programs fast
+    let allocator = if args.iter().any(|flag| flag == "--linear-scan") {
+        Allocator::LinearScan
+    } else {
+        Allocator::Colouring
+    };
+    let mut program = mellow_assembly::convert(cfg, allocator);
+    let (text, statistics) = mellow_assembly::optimize(program.text);
+    program.text = text;
+    if args.iter().any(|flag| flag == "--peephole-stats") {
+        eprint!("{statistics}");
     }
 
-    println!("section .text");
-    println!("global _start:");
-    println!("_start:");
+    let syntax = match args
+        .iter()
+        .find_map(|flag| flag.strip_prefix("--asm-syntax="))
+    {
+        Some(name) => Syntax::from_name(name).unwrap_or_else(|| {
+            eprintln!("error: unknown assembly syntax {name}, expected nasm or gas");
+            exit(1);
+        }),
+        None => Syntax::default(),
+    };
 
-    for (identifier, meta) in symbol_table.functions() {
-        if meta.external {
-            println!("extern {}", identifier.name);
+    let output = value("-o");
+    if build {
+        let runtime_path = value("--runtime").map_or("sl/output/main.o", String::as_str);
+        let runtime = match fs::read(runtime_path) {
+            Ok(runtime) => runtime,
+            Err(error) => {
+                eprintln!("error: cannot read runtime {runtime_path}: {error}");
+                exit(1);
+            }
+        };
+        let executable = match program.executable(&[&runtime]) {
+            Ok(executable) => executable,
+            Err(error) => {
+                eprintln!("error: {error}");
+                exit(1);
+            }
+        };
+        let path = output.map_or("main", String::as_str);
+        if let Err(error) = write_executable(path, &executable) {
+            eprintln!("error: cannot write {path}: {error}");
+            exit(1);
         }
+    } else if args.iter().any(|flag| flag == "-c") {
+        let path = output.map_or("output.o", String::as_str);
+        if let Err(error) = fs::write(path, program.object()) {
+            eprintln!("error: cannot write {path}: {error}");
+            exit(1);
+        }
+    } else {
+        print!("{}", program.listing(syntax));
     }
+}
 
-    let assembly = assembly::convert(tac);
-    let assembly = assembly::optimize(assembly);
-    for instruction in assembly {
-        println!("{instruction}")
-    }
-
-    println!("mov rax, 60");
-    println!("mov rdi, 0");
-    println!("syscall");
+fn write_executable(path: &str, bytes: &[u8]) -> io::Result<()> {
+    fs::write(path, bytes)?;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
 }
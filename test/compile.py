@@ -1,8 +1,27 @@
-import subprocess
 import os
+import subprocess
+
+# programs also checked in other compiler modes, by snapshot suffix
+VARIANTS = {
+    "linear-scan": ("--linear-scan", ["call.mellow", "select.mellow", "spill.mellow", "ssa.mellow"]),
+    "peephole": ("--peephole-stats", ["const.mellow", "ssa.mellow"]),
+    "gas": ("--asm-syntax=gas", ["frame.mellow", "select.mellow", "string.mellow"]),
+}
+
+
+def snapshots():
+    """Yields the name, source and compiler arguments of every snapshot."""
+    for file in sorted(os.listdir("source")):
+        yield file, f"source/{file}", ""
+    for suffix, (arguments, files) in VARIANTS.items():
+        for file in files:
+            name = file.replace(".mellow", f".{suffix}.mellow")
+            yield name, f"source/{file}", arguments
 
-DEV_NULL = open(os.devnull, "w")
 
-def compile(path):
-    output = subprocess.check_output(f"cargo run -- {path}", shell=True, stderr=DEV_NULL)
-    return output.decode("utf-8")
+def compile(path, arguments=""):
+    """What the compiler prints to stdout, followed by its warnings and errors."""
+    result = subprocess.run(
+        f"cargo run -q -- {path} {arguments}", shell=True, capture_output=True
+    )
+    return result.stdout.decode("utf-8") + result.stderr.decode("utf-8")
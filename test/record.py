@@ -1,7 +1,6 @@
-import os
-from compile import compile
+from compile import compile, snapshots
 
-for file in os.listdir("source"):
-    output = compile(f"source/{file}")
-    with open(f"output/{file}", "w") as output_file:
+for name, path, arguments in snapshots():
+    output = compile(path, arguments)
+    with open(f"output/{name}", "w") as output_file:
         output_file.write(output)
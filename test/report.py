@@ -1,9 +1,9 @@
-import os
+from compile import snapshots
 
-for name in os.listdir("source"):
-    source = open(f"source/{name}").read()
+for name, path, arguments in snapshots():
+    source = open(path).read()
     output = open(f"output/{name}").read()
-    print(f"=== {name} ===")
+    print(f"=== {name} {arguments} ===")
     print(source)
     print(f"--- output ---")
     print(output)
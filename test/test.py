@@ -1,10 +1,101 @@
 import os
-from compile import compile
-
-for file in os.listdir("source"):
-    output = compile(f"source/{file}")
-    expected = open(f"output/{file}").read()
-    if output == expected:
-        print(f"[{file}]: OK")
-    else:
-        print(f"[{file}]: FAIL")
+import shutil
+import subprocess
+import sys
+import tempfile
+from compile import compile, snapshots
+
+failed = False
+
+
+def report(name, ok):
+    global failed
+    print(f"[{name}]: {'OK' if ok else 'FAIL'}")
+    failed = failed or not ok
+
+
+def run(command):
+    return subprocess.run(command, shell=True, capture_output=True)
+
+
+def execute(path):
+    """The output and exit code of a program."""
+    result = run(path)
+    return result.stdout, result.returncode
+
+
+def link(directory, object):
+    """Links `object` with the runtime the way the Makefile used to."""
+    path = f"{directory}/main"
+    run(f"gcc -nostdlib -no-pie {object} ../sl/output/main.o -o {path}").check_returncode()
+    return path
+
+
+def fails(arguments, message):
+    """Whether the compiler stops with `message` when given `arguments`."""
+    result = run(f"cargo run -q -- {arguments}")
+    return result.returncode == 1 and message in result.stderr.decode("utf-8")
+
+
+for name, path, arguments in snapshots():
+    output = compile(path, arguments)
+    expected = open(f"output/{name}").read()
+    report(name, output == expected)
+
+# programs that compile are also assembled, linked and run
+run("make -s -C ../sl compile").check_returncode()
+with tempfile.TemporaryDirectory() as directory:
+    for file in sorted(os.listdir("source")):
+        object = f"{directory}/main.o"
+        if run(f"cargo run -q -- source/{file} -c -o {object}").returncode != 0:
+            continue
+        native = execute(link(directory, object))
+
+        built = f"{directory}/built"
+        runtime = "--runtime ../sl/output/main.o"
+        result = run(f"cargo run -q -- build source/{file} -o {built} {runtime}")
+        report(f"{file} build", result.returncode == 0 and execute(built) == native)
+
+        run(f"cargo run -q -- source/{file} --asm-syntax=gas > {directory}/main.s").check_returncode()
+        assembled = run(f"gcc -c {directory}/main.s -o {object}")
+        report(f"{file} gas", assembled.returncode == 0
+               and execute(link(directory, object)) == native)
+
+        if shutil.which("nasm"):
+            run(f"cargo run -q -- source/{file} > {directory}/main.asm").check_returncode()
+            assembled = run(f"nasm -f elf64 {directory}/main.asm -o {object}")
+            report(f"{file} -c as nasm", assembled.returncode == 0
+                   and execute(link(directory, object)) == native)
+        else:
+            print(f"[{file} -c as nasm]: SKIP (no nasm)")
+
+
+with tempfile.TemporaryDirectory() as directory:
+    build = f"build source/string.mellow -o {directory}/main --runtime"
+    report("build without a runtime",
+           fails(f"{build} {directory}/missing.o", "error: cannot read runtime"))
+    report("build with a runtime that is not an object",
+           fails(f"{build} source/string.mellow", "error: malformed object"))
+    report("build with a runtime that lacks a routine",
+           fails(f"{build} ../sl/output/math.o", "error: undefined reference to `debug_s`"))
+
+# a long generated program has to compile in linear time and memory with linear scan
+with tempfile.TemporaryDirectory() as directory:
+    count = 12000
+    with open(f"{directory}/long.mellow", "w") as source:
+        for index in range(count):
+            source.write(f"let mutable v{index} = {index}\n")
+            source.write(f"if v{index} > 3 then\n    v{index} += 1\nend\n")
+        source.write(f"debug v{count - 1}\n")
+    # the limits are for the compiler, not for cargo building it
+    run("cargo build -q").check_returncode()
+    limits = "ulimit -v 524288 && ulimit -t 60"  # 512 MiB and a minute of CPU time
+    object = f"{directory}/main.o"
+    result = run(f"{limits} && ../target/debug/mellow {directory}/long.mellow --linear-scan -c -o {object}")
+    report("long generated program", result.returncode == 0
+           and execute(link(directory, object)) == (f"{count}\n".encode(), 0))
+
+report("unknown assembly syntax",
+       fails("source/string.mellow --asm-syntax=att", "error: unknown assembly syntax att"))
+
+sys.exit(1 if failed else 0)